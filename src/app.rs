@@ -1,23 +1,106 @@
-use rayon::ThreadPoolBuilder;
-
+use crate::ledger::load_interrupted;
+use crate::coordinator::Coordinator;
+use crate::joblog;
+use crate::processing::{start_workers, JobSettings, Queue};
+use crate::resources::{join_cgroup, parse_size};
+use crate::shutdown;
 use crate::watcher;
+use crate::worker::{hostname, RemoteWorker};
 use crate::config;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tiny_http::Server;
 
 pub fn start_transcoding_app() {
     let cfg = config::load_config();
-    ThreadPoolBuilder::new()
-        .num_threads(cfg.threads)
-        .build_global()
-        .unwrap();
-
     println!("🎯 Watching directory: {}", cfg.watch_dir);
     println!("📡 SMB mode: {}", cfg.is_smb);
-    println!("🧵 Using {} threads", cfg.threads);
+    println!("🧵 Using {} workers", cfg.threads);
+    let queue = start_common(&cfg);
+
+    // Watchers only scan and queue; this fixed pool of workers does the encoding
+    let _workers = start_workers(&queue, cfg.threads);
+    watch(&cfg, &queue);
+}
 
+/// Watches like `start_transcoding_app`, but hands the queued jobs to remote
+/// workers instead of encoding them here.
+pub fn start_coordinator_app() {
+    let cfg = config::load_config();
+    println!("🎯 Watching directory: {}", cfg.watch_dir);
+    let queue = start_common(&cfg);
+
+    let server = match Server::http(&cfg.coordinator_addr) {
+        Ok(server) => server,
+        Err(e) => {
+            println!("❌ Failed to listen on {}: {}", cfg.coordinator_addr, e);
+            return;
+        }
+    };
+    println!("🛰️ Coordinating workers on {}", cfg.coordinator_addr);
+    let coordinator = Arc::new(Coordinator::new(Arc::clone(&queue), Duration::from_secs(cfg.heartbeat_secs * 3)));
+    coordinator.start_reaper();
+    let serving = Arc::clone(&coordinator);
+    thread::spawn(move || serving.serve(server));
+
+    watch(&cfg, &queue);
+}
+
+/// Runs jobs from the coordinator at `url` (or `COORDINATOR_URL`) until shut down.
+pub fn start_worker_app(url: Option<&str>) {
+    let cfg = config::load_config();
+    let url = url.unwrap_or(&cfg.coordinator_url);
+    let name = cfg.worker_name.clone().unwrap_or_else(|| format!("{}-{}", hostname(), std::process::id()));
+    println!("🛰️ Worker {} taking jobs from {}", name, url);
+    println!("🧵 Running {} jobs at a time", cfg.threads);
+    shutdown::install(Duration::from_secs(cfg.shutdown_grace_secs));
+    join_configured_cgroup(&cfg);
+
+    let worker = Arc::new(RemoteWorker::new(
+        url,
+        &name,
+        Path::new(&cfg.worker_dir),
+        cfg.shared_paths,
+        Duration::from_secs(cfg.heartbeat_secs),
+    ));
+    for handle in worker.start(cfg.threads) {
+        let _ = handle.join();
+    }
+}
+
+/// Signal handling, resource limits and the job queue shared by local and coordinated modes.
+fn start_common(cfg: &config::AppConfig) -> Arc<Queue> {
+    join_configured_cgroup(cfg);
+    shutdown::install(Duration::from_secs(cfg.shutdown_grace_secs));
+    let interrupted = load_interrupted();
+    if !interrupted.is_empty() {
+        println!("💾 {} job(s) were interrupted last time and will resume first", interrupted.len());
+    }
+    joblog::start_pruning(cfg.log_dir.clone().into(), cfg.log_retention_days);
+    Arc::new(Queue::new())
+}
+
+fn watch(cfg: &config::AppConfig, queue: &Arc<Queue>) {
+    let settings = Arc::new(JobSettings::from_config(cfg));
     if cfg.is_smb {
-        watcher::start_watch_with_fallback(&cfg.watch_dir);
+        watcher::start_watch_with_fallback(&cfg.watch_dir, queue, &settings);
     } else {
-        watcher::start_watch(&cfg.watch_dir);
+        watcher::start_watch(&cfg.watch_dir, queue, &settings);
+    }
+}
+
+/// Done before any ffmpeg starts, so every encode inherits the quota.
+fn join_configured_cgroup(cfg: &config::AppConfig) {
+    if let Some(cgroup) = &cfg.cgroup {
+        let memory = cfg.cgroup_memory.as_deref().and_then(|v| {
+            parse_size(v).map_err(|e| println!("⚠️ Ignoring CGROUP_MEMORY: {}", e)).ok()
+        });
+        match join_cgroup(cgroup, cfg.cgroup_cpus, memory) {
+            Ok(dir) => println!("📏 Running in cgroup {}", dir.display()),
+            Err(e) => println!("⚠️ Failed to set up cgroup {}: {}", cgroup, e),
+        }
     }
 }
 
@@ -0,0 +1,264 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Scene score above which a frame counts as a cut (ffmpeg's `scene` filter value).
+const SCENE_THRESHOLD: f64 = 0.4;
+/// How far from a scene cut a keyframe may be and still count as on it.
+const SCENE_SNAP_SECS: f64 = 1.0;
+
+/// Optional chunked encoding: long sources are split at keyframes and the
+/// pieces encoded in parallel, then joined without re-encoding.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChunkSettings {
+    /// Target segment length; 0 disables chunking.
+    pub segment_secs: u64,
+    /// Segments encoded at once for one source.
+    pub workers: usize,
+    /// Prefer split points at scene cuts (needs an extra decode of the source).
+    pub scene_aware: bool,
+}
+
+impl ChunkSettings {
+    /// Chunking only pays off when there are at least two segments to run at once.
+    pub fn applies_to(&self, duration: f64) -> bool {
+        self.segment_secs > 0 && self.workers > 1 && duration >= 2.0 * self.segment_secs as f64
+    }
+}
+
+/// `start..end` seconds of the source, encoded as one piece.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub index: usize,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Decides where to split `input`: at keyframes roughly `segment_secs` apart,
+/// preferring scene cuts when enabled. Returns no segments if the source can't
+/// be split usefully.
+pub fn plan_segments(input: &Path, duration: f64, settings: &ChunkSettings) -> Vec<Segment> {
+    let keyframes = match probe_keyframes(input) {
+        Ok(keyframes) => keyframes,
+        Err(e) => {
+            println!("⚠️ Could not list keyframes, encoding in one piece: {}", e);
+            return Vec::new();
+        }
+    };
+    let scenes = if settings.scene_aware {
+        detect_scenes(input).unwrap_or_else(|e| {
+            println!("⚠️ Scene detection failed, splitting at keyframes only: {}", e);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+
+    let points = split_points(&keyframes, duration, settings.segment_secs as f64, &scenes);
+    if points.is_empty() {
+        return Vec::new();
+    }
+    let bounds: Vec<f64> = std::iter::once(0.0).chain(points).chain(std::iter::once(duration)).collect();
+    bounds
+        .windows(2)
+        .enumerate()
+        .map(|(index, pair)| Segment { index, start: pair[0], end: pair[1] })
+        .collect()
+}
+
+/// Runs `encode` on every segment, `workers` at a time, stopping at the first
+/// error, which is returned.
+pub fn run_parallel<E: Send>(
+    segments: &[Segment],
+    workers: usize,
+    encode: impl Fn(&Segment) -> Result<(), E> + Sync,
+) -> Result<(), E> {
+    let next = AtomicUsize::new(0);
+    let failure: Mutex<Option<E>> = Mutex::new(None);
+    let failed = || failure.lock().unwrap_or_else(|e| e.into_inner()).is_some();
+
+    thread::scope(|scope| {
+        for _ in 0..workers.clamp(1, segments.len().max(1)) {
+            scope.spawn(|| {
+                while !failed() {
+                    let Some(segment) = segments.get(next.fetch_add(1, Ordering::SeqCst)) else {
+                        break;
+                    };
+                    if let Err(e) = encode(segment) {
+                        failure.lock().unwrap_or_else(|e| e.into_inner()).get_or_insert(e);
+                    }
+                }
+            });
+        }
+    });
+
+    match failure.into_inner().unwrap_or_else(|e| e.into_inner()) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Picks split points from `keyframes`: for each segment, the keyframe closest
+/// to `target` seconds after the previous split, within half a target either way.
+/// A keyframe near a scene cut wins over a closer one that isn't. Less than one
+/// and a half targets of remaining time stays one segment, and no segment is
+/// shorter than half a target.
+pub fn split_points(keyframes: &[f64], duration: f64, target: f64, scenes: &[f64]) -> Vec<f64> {
+    let near_scene = |time: f64| scenes.iter().any(|s| (s - time).abs() <= SCENE_SNAP_SECS);
+    let mut points = Vec::new();
+    let mut previous = 0.0;
+    loop {
+        let ideal = previous + target;
+        if ideal + target / 2.0 > duration {
+            break;
+        }
+        let candidates = keyframes
+            .iter()
+            .copied()
+            .filter(|&k| k >= previous + target / 2.0 && k <= (ideal + target / 2.0).min(duration - target / 2.0));
+        let best = candidates.min_by(|a, b| {
+            let key = |k: f64| (!near_scene(k), (k - ideal).abs());
+            key(*a).partial_cmp(&key(*b)).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        match best {
+            Some(point) => {
+                points.push(point);
+                previous = point;
+            }
+            None => break,
+        }
+    }
+    points
+}
+
+/// Lists the presentation times of the video keyframes in `input`.
+pub fn probe_keyframes(input: &Path) -> io::Result<Vec<f64>> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v", "error",
+            "-select_streams", "v:0",
+            "-skip_frame", "nokey",
+            "-show_entries", "frame=pts_time",
+            "-of", "csv=p=0",
+        ])
+        .arg(input)
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    Ok(parse_keyframes(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn parse_keyframes(text: &str) -> Vec<f64> {
+    let mut times: Vec<f64> = text.lines().filter_map(|l| l.trim().trim_end_matches(',').parse().ok()).collect();
+    times.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    times
+}
+
+/// Finds scene cuts in `input` by decoding it once with ffmpeg's scene filter.
+pub fn detect_scenes(input: &Path) -> io::Result<Vec<f64>> {
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(input)
+        .args([
+            "-map", "0:v:0",
+            "-vf", &format!("select='gt(scene,{})',showinfo", SCENE_THRESHOLD),
+            "-f", "null", "-",
+        ])
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("ffmpeg exited with {}", output.status)));
+    }
+    Ok(parse_scenes(&String::from_utf8_lossy(&output.stderr)))
+}
+
+/// Pulls `pts_time:` values out of showinfo lines.
+fn parse_scenes(stderr: &str) -> Vec<f64> {
+    stderr
+        .lines()
+        .filter(|l| l.contains("Parsed_showinfo"))
+        .filter_map(|l| l.split("pts_time:").nth(1))
+        .filter_map(|rest| rest.split_whitespace().next()?.parse().ok())
+        .collect()
+}
+
+/// Writes an ffmpeg concat-demuxer list of `files` into `dir`.
+pub fn write_concat_list(dir: &Path, files: &[PathBuf]) -> io::Result<PathBuf> {
+    let list = dir.join("segments.txt");
+    let body: String = files
+        .iter()
+        .map(|f| format!("file '{}'\n", f.to_string_lossy().replace('\'', "'\\''")))
+        .collect();
+    fs::write(&list, body)?;
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slots::{self, SlotLimits};
+    use std::time::Duration;
+
+    #[test]
+    fn test_split_points_follow_keyframes_and_scenes() {
+        // A keyframe every 2s over ten minutes
+        let keyframes: Vec<f64> = (0..300).map(|i| i as f64 * 2.0).collect();
+        assert_eq!(split_points(&keyframes, 600.0, 120.0, &[]), vec![120.0, 240.0, 360.0, 480.0]);
+
+        // A cut at 131s pulls the first split off the ideal point
+        let points = split_points(&keyframes, 600.0, 120.0, &[131.3]);
+        assert_eq!(points[0], 132.0);
+        assert_eq!(points[1], 252.0);
+
+        // Too short for two segments, or no keyframes in range
+        assert!(split_points(&keyframes, 100.0, 120.0, &[]).is_empty());
+        assert!(split_points(&[0.0, 599.0], 600.0, 120.0, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_parse_probe_output() {
+        assert_eq!(parse_keyframes("0.000000\n10.010000,\nN/A\n5.005000\n"), vec![0.0, 5.005, 10.01]);
+
+        let stderr = "[Parsed_showinfo_1 @ 0x5581] n:   0 pts:  93093 pts_time:93.093  duration:1001\n\
+                      frame=  100 fps=0.0\n\
+                      [Parsed_showinfo_1 @ 0x5581] n:   1 pts: 412412 pts_time:412.412 duration:1001\n";
+        assert_eq!(parse_scenes(stderr), vec![93.093, 412.412]);
+    }
+
+    #[test]
+    fn test_write_concat_list_quotes_paths() {
+        let dir = std::env::temp_dir().join("test_concat_list");
+        fs::create_dir_all(&dir).unwrap();
+        let list = write_concat_list(&dir, &[dir.join("0000.mkv"), dir.join("it's.mkv")]).unwrap();
+        let body = fs::read_to_string(&list).unwrap();
+        assert_eq!(
+            body,
+            format!("file '{0}/0000.mkv'\nfile '{0}/it'\\''s.mkv'\n", dir.display())
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_segments_run_side_by_side_within_one_slot() {
+        // The job's one CPU slot under the default limits covers all its segments
+        let _slot = slots::acquire(&SlotLimits::default(), &[], false);
+        let segments: Vec<Segment> = (0..4).map(|index| Segment { index, start: 0.0, end: 1.0 }).collect();
+        let running = AtomicUsize::new(0);
+        let most = AtomicUsize::new(0);
+        let result = run_parallel(&segments, 2, |_| {
+            most.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(200));
+            running.fetch_sub(1, Ordering::SeqCst);
+            Ok::<(), String>(())
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+
+        let failed = run_parallel(&segments, 2, |s| if s.index == 1 { Err(s.index) } else { Ok(()) });
+        assert_eq!(failed, Err(1));
+    }
+}
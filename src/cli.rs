@@ -0,0 +1,231 @@
+use crate::app;
+use crate::config::load_config;
+use crate::ledger::{clear_failed, load_failed};
+use crate::preview::{self, preview_file, PreviewSettings};
+use crate::profile::{active_profile, load_profile};
+use crate::sidecar::{self, load_file_options, save_file_options};
+use crate::transcode::JobOptions;
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "\
+Usage: video_transcoder [COMMAND]
+
+Commands:
+  watch                                 Watch WATCH_DIR and convert new files (default)
+  coordinator                           Watch WATCH_DIR and hand jobs to remote workers
+  worker [URL]                          Take jobs from the coordinator at URL (or COORDINATOR_URL)
+  subtitle-sync <video> [OPTIONS]       Store subtitle timing fixes in the video's sidecar
+      --offset <SECONDS|auto>           Shift subtitles, or estimate the shift from the audio
+      --fps <FROM:TO>                   Retime subtitles authored at FROM fps for a TO fps video
+      --clear                           Remove all subtitle timing fixes
+  preview <file>... [--profile NAME]    Encode short samples and project size and speed
+  approve-preview <profile>             Let the watcher use a previewed profile (REQUIRE_PREVIEW)
+  failures                              List failed files and when they are retried
+  reset <file|name>...                  Forget failures so files are retried on the next scan
+  help                                  Show this message";
+
+/// Dispatches command line arguments (without the program name). Returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    match args.first().map(String::as_str) {
+        None | Some("watch") => {
+            app::start_transcoding_app();
+            0
+        }
+        Some("coordinator") => {
+            app::start_coordinator_app();
+            0
+        }
+        Some("worker") => {
+            app::start_worker_app(args.get(1).map(String::as_str));
+            0
+        }
+        Some("subtitle-sync") => subtitle_sync(&args[1..]),
+        Some("preview") => preview(&args[1..]),
+        Some("approve-preview") => approve_preview(&args[1..]),
+        Some("failures") => failures(),
+        Some("reset") => reset(&args[1..]),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            0
+        }
+        Some(other) => {
+            eprintln!("Unknown command: {}\n\n{}", other, USAGE);
+            2
+        }
+    }
+}
+
+fn subtitle_sync(args: &[String]) -> i32 {
+    let Some(video) = args.first() else {
+        eprintln!("subtitle-sync needs a video path\n\n{}", USAGE);
+        return 2;
+    };
+    let video = Path::new(video);
+    let mut options = load_file_options(video);
+
+    let mut rest = args[1..].iter();
+    while let Some(flag) = rest.next() {
+        let result = match flag.as_str() {
+            "--offset" => rest
+                .next()
+                .ok_or_else(|| "--offset needs a value".to_string())
+                .and_then(|v| sidecar::parse_offset(v))
+                .map(|o| options.subtitle_offset = Some(o)),
+            "--fps" => rest
+                .next()
+                .ok_or_else(|| "--fps needs a value".to_string())
+                .and_then(|v| sidecar::parse_fps_pair(v))
+                .map(|f| options.subtitle_fps = Some(f)),
+            "--clear" => {
+                options.subtitle_offset = None;
+                options.subtitle_fps = None;
+                Ok(())
+            }
+            other => Err(format!("unknown option {}", other)),
+        };
+        if let Err(e) = result {
+            eprintln!("❌ {}", e);
+            return 2;
+        }
+    }
+
+    match save_file_options(video, &options) {
+        Ok(()) => {
+            println!("📝 Saved {}", sidecar::sidecar_path(video).display());
+            0
+        }
+        Err(e) => {
+            eprintln!("❌ Failed to write sidecar: {}", e);
+            1
+        }
+    }
+}
+
+fn preview(args: &[String]) -> i32 {
+    let mut files = Vec::new();
+    let mut profile_name = None;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--profile" => match rest.next() {
+                Some(name) => profile_name = Some(name.clone()),
+                None => {
+                    eprintln!("❌ --profile needs a value");
+                    return 2;
+                }
+            },
+            flag if flag.starts_with("--") => {
+                eprintln!("❌ unknown option {}", flag);
+                return 2;
+            }
+            file => files.push(PathBuf::from(file)),
+        }
+    }
+    if files.is_empty() {
+        eprintln!("preview needs at least one video\n\n{}", USAGE);
+        return 2;
+    }
+    let profile = match profile_name.map(|name| load_profile(&name)) {
+        Some(Ok(profile)) => profile,
+        Some(Err(e)) => {
+            eprintln!("❌ {}", e);
+            return 2;
+        }
+        None => active_profile(),
+    };
+
+    let cfg = load_config();
+    let settings = PreviewSettings { samples: cfg.preview_samples, sample_secs: cfg.preview_secs as f64 };
+    let detected = JobOptions { profile, ..JobOptions::detect() };
+    let mut code = 0;
+    for file in &files {
+        // Same subtitle pairing as the watcher: an SRT with the video's stem
+        let srt = file.with_extension("srt");
+        let options = JobOptions { subtitle: srt.exists().then_some(srt), ..detected.clone() };
+        match preview_file(file, &options, &settings) {
+            Ok(report) => {
+                let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+                let projected = report.projected_size();
+                let speed = report.speed();
+                println!(
+                    "🔍 {}: ~{:.1} MiB ({:.0}% of source), {:.1}x realtime, ~{:.0} min to encode",
+                    file.display(),
+                    mib(projected),
+                    projected as f64 * 100.0 / report.source_size.max(1) as f64,
+                    speed,
+                    if speed > 0.0 { report.duration / speed / 60.0 } else { 0.0 }
+                );
+                println!("📁 {}", preview::report_path(file, &report.profile).display());
+            }
+            Err(e) => {
+                eprintln!("❌ Preview of {} failed: {}", file.display(), e);
+                code = 1;
+            }
+        }
+    }
+    if code == 0 {
+        println!("👉 If the samples look right: video_transcoder approve-preview {}", detected.profile.name);
+    }
+    code
+}
+
+/// Records the profile's current settings as approved; editing it later needs a new approval.
+fn approve_preview(args: &[String]) -> i32 {
+    let Some(name) = args.first() else {
+        eprintln!("approve-preview needs a profile name\n\n{}", USAGE);
+        return 2;
+    };
+    let profile = match load_profile(name) {
+        Ok(profile) => profile,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            return 2;
+        }
+    };
+    match preview::approve(&profile) {
+        Ok(()) => {
+            println!("✅ Approved profile {} ({})", profile.name, profile.fingerprint());
+            0
+        }
+        Err(e) => {
+            eprintln!("❌ Failed to record approval: {}", e);
+            1
+        }
+    }
+}
+
+fn failures() -> i32 {
+    let mut failed: Vec<_> = load_failed().into_iter().collect();
+    failed.sort_by(|a, b| a.0.cmp(&b.0));
+    for (entry, record) in failed {
+        let next = match record.next_retry {
+            Some(at) => format!("retry at {}", at.format("%Y-%m-%d %H:%M:%S")),
+            None => "permanent".to_string(),
+        };
+        println!("{}\t{} attempt(s), {}\t{}", entry, record.attempts, next, record.last_error);
+    }
+    0
+}
+
+/// Accepts ledger names as well as paths, since the ledger is keyed by file stem.
+fn reset(args: &[String]) -> i32 {
+    if args.is_empty() {
+        eprintln!("reset needs at least one file or name\n\n{}", USAGE);
+        return 2;
+    }
+
+    let mut code = 0;
+    for arg in args {
+        let entry = Path::new(arg)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| arg.clone());
+        if clear_failed(&entry) {
+            println!("🔄 Reset {}", entry);
+        } else {
+            eprintln!("❓ No failures recorded for {}", entry);
+            code = 1;
+        }
+    }
+    code
+}
@@ -2,6 +2,70 @@ pub struct AppConfig {
     pub watch_dir: String,
     pub is_smb: bool,
     pub threads: usize,
+    pub log_dir: String,
+    pub log_retention_days: u64,
+    pub quarantine_dir: String,
+    /// Seconds a source must stay unchanged before it is picked up.
+    pub stable_secs: u64,
+    /// Seconds between rescans when no file events arrive, so deferred jobs are tried again.
+    pub rescan_secs: u64,
+    /// Folder with job lifecycle hook scripts, if any.
+    pub hook_dir: Option<String>,
+    pub hook_timeout_secs: u64,
+    /// How long a pre-hook veto stands before an unchanged source is offered again.
+    pub hook_veto_recheck_secs: u64,
+    /// Endpoints that receive job events as JSON.
+    pub webhook_urls: Vec<String>,
+    pub webhook_secret: Option<String>,
+    pub webhook_retries: u32,
+    /// Log of every webhook delivery attempt; kept apart from the pruned job logs.
+    pub webhook_log: String,
+    /// ffmpeg may run this many times the source duration (but at least
+    /// `min_timeout_secs`) and stand still for at most `stall_secs`.
+    pub timeout_factor: f64,
+    pub min_timeout_secs: u64,
+    pub stall_secs: u64,
+    /// Failed files are retried with exponential backoff from `retry_base_secs`
+    /// up to `retry_max_secs`, and given up on after `max_attempts`.
+    pub max_attempts: u32,
+    pub retry_base_secs: u64,
+    pub retry_max_secs: u64,
+    /// How long running jobs may finish after SIGINT/SIGTERM before they are stopped.
+    pub shutdown_grace_secs: u64,
+    /// Concurrent jobs per backend and device, e.g. `nvenc=3,vaapi:/dev/dri/renderD129=1`.
+    pub slot_limits: String,
+    /// Priority of spawned ffmpeg: niceness, I/O class (`idle`, `best-effort:N`,
+    /// `realtime:N`) and the CPUs it may use (`0-3,6`).
+    pub ffmpeg_nice: Option<i32>,
+    pub ffmpeg_ionice: Option<String>,
+    pub ffmpeg_cpus: Option<String>,
+    /// ffmpeg `-threads` per job, so parallel jobs don't each take every core.
+    pub ffmpeg_threads: Option<usize>,
+    /// cgroup v2 group (under /sys/fs/cgroup) the whole worker runs in, with an
+    /// optional quota in CPUs (e.g. `2.5`) and memory (e.g. `8G`).
+    pub cgroup: Option<String>,
+    pub cgroup_cpus: Option<f64>,
+    pub cgroup_memory: Option<String>,
+    /// Split sources longer than two segments of `chunk_secs` (0 = never) and encode
+    /// up to `chunk_workers` pieces at once, optionally splitting at scene cuts.
+    pub chunk_secs: u64,
+    pub chunk_workers: usize,
+    pub chunk_scenes: bool,
+    /// Where the coordinator listens for workers, and where workers find it.
+    pub coordinator_addr: String,
+    pub coordinator_url: String,
+    pub worker_name: Option<String>,
+    /// Where workers keep downloaded sources while encoding them.
+    pub worker_dir: String,
+    /// Workers use the coordinator's paths directly when they exist locally too.
+    pub shared_paths: bool,
+    /// Workers report every `heartbeat_secs`; after three missed beats their job is reassigned.
+    pub heartbeat_secs: u64,
+    /// Previews encode `preview_samples` clips of `preview_secs` from each file.
+    pub preview_samples: usize,
+    pub preview_secs: u64,
+    /// The watcher only queues jobs once the active profile has an approved preview.
+    pub require_preview: bool,
 }
 
 pub fn load_config() -> AppConfig {
@@ -15,7 +79,185 @@ pub fn load_config() -> AppConfig {
         .and_then(|v| v.parse().ok())
         .unwrap_or_else(|| std::cmp::max(1, num_cpus::get() / 2));
 
-    AppConfig { watch_dir, is_smb, threads }
+    let log_dir = std::env::var("LOG_DIR").unwrap_or_else(|_| "/var/tmp/video_transcoder_logs".into());
+    let log_retention_days = std::env::var("LOG_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(14);
+
+    let quarantine_dir = std::env::var("QUARANTINE_DIR")
+        .unwrap_or_else(|_| "/var/tmp/video_transcoder_quarantine".into());
+
+    let stable_secs = std::env::var("STABLE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(15);
+    let rescan_secs = std::env::var("RESCAN_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(300);
+
+    let hook_dir = std::env::var("HOOK_DIR").ok().filter(|d| !d.is_empty());
+    let hook_timeout_secs = std::env::var("HOOK_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30);
+    let hook_veto_recheck_secs = std::env::var("HOOK_VETO_RECHECK_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(600);
+
+    let webhook_urls = std::env::var("WEBHOOK_URLS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|u| !u.is_empty())
+        .map(String::from)
+        .collect();
+    let webhook_secret = std::env::var("WEBHOOK_SECRET").ok().filter(|s| !s.is_empty());
+    let webhook_retries = std::env::var("WEBHOOK_RETRIES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3);
+    let webhook_log = std::env::var("WEBHOOK_LOG").unwrap_or_else(|_| "/var/tmp/webhook_deliveries.log".into());
+
+    let timeout_factor = std::env::var("TIMEOUT_FACTOR")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|f: &f64| f.is_finite() && *f > 0.0)
+        .unwrap_or(4.0);
+    let min_timeout_secs = std::env::var("MIN_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1800);
+    let stall_secs = std::env::var("STALL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(180);
+
+    let max_attempts = std::env::var("MAX_ATTEMPTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(5);
+    let retry_base_secs = std::env::var("RETRY_BASE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(600);
+    let retry_max_secs = std::env::var("RETRY_MAX_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(24 * 60 * 60);
+
+    let shutdown_grace_secs = std::env::var("SHUTDOWN_GRACE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30);
+
+    let slot_limits = std::env::var("SLOT_LIMITS").unwrap_or_default();
+
+    let ffmpeg_nice = std::env::var("FFMPEG_NICE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n: &i32| (-20..=19).contains(n));
+    let ffmpeg_ionice = std::env::var("FFMPEG_IONICE").ok().filter(|v| !v.is_empty());
+    let ffmpeg_cpus = std::env::var("FFMPEG_CPUS").ok().filter(|v| !v.is_empty());
+    let ffmpeg_threads = std::env::var("FFMPEG_THREADS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0);
+
+    let cgroup = std::env::var("CGROUP").ok().filter(|v| !v.is_empty());
+    let cgroup_cpus = std::env::var("CGROUP_CPUS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n: &f64| *n > 0.0);
+    let cgroup_memory = std::env::var("CGROUP_MEMORY").ok().filter(|v| !v.is_empty());
+
+    let chunk_secs = std::env::var("CHUNK_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let chunk_workers = std::env::var("CHUNK_WORKERS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(threads);
+    let chunk_scenes = std::env::var("CHUNK_SCENES")
+        .unwrap_or_else(|_| "false".into())
+        .to_lowercase() == "true";
+
+    let coordinator_addr = std::env::var("COORDINATOR_ADDR").unwrap_or_else(|_| "0.0.0.0:7878".into());
+    let coordinator_url = std::env::var("COORDINATOR_URL").unwrap_or_else(|_| "http://127.0.0.1:7878".into());
+    let worker_name = std::env::var("WORKER_NAME").ok().filter(|n| !n.is_empty());
+    let worker_dir = std::env::var("WORKER_DIR").unwrap_or_else(|_| "/var/tmp/video_transcoder_worker".into());
+    let shared_paths = std::env::var("SHARED_PATHS")
+        .unwrap_or_else(|_| "false".into())
+        .to_lowercase() == "true";
+    let heartbeat_secs = std::env::var("HEARTBEAT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(10);
+
+    let preview_samples = std::env::var("PREVIEW_SAMPLES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(3);
+    let preview_secs = std::env::var("PREVIEW_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(20);
+    let require_preview = std::env::var("REQUIRE_PREVIEW")
+        .unwrap_or_else(|_| "false".into())
+        .to_lowercase() == "true";
+
+    AppConfig {
+        watch_dir,
+        is_smb,
+        threads,
+        log_dir,
+        log_retention_days,
+        quarantine_dir,
+        stable_secs,
+        rescan_secs,
+        hook_dir,
+        hook_timeout_secs,
+        hook_veto_recheck_secs,
+        webhook_urls,
+        webhook_secret,
+        webhook_retries,
+        webhook_log,
+        timeout_factor,
+        min_timeout_secs,
+        stall_secs,
+        max_attempts,
+        retry_base_secs,
+        retry_max_secs,
+        shutdown_grace_secs,
+        slot_limits,
+        ffmpeg_nice,
+        ffmpeg_ionice,
+        ffmpeg_cpus,
+        ffmpeg_threads,
+        cgroup,
+        cgroup_cpus,
+        cgroup_memory,
+        chunk_secs,
+        chunk_workers,
+        chunk_scenes,
+        coordinator_addr,
+        coordinator_url,
+        worker_name,
+        worker_dir,
+        shared_paths,
+        heartbeat_secs,
+        preview_samples,
+        preview_secs,
+        require_preview,
+    }
 }
 
 #[cfg(test)]
@@ -32,8 +274,8 @@ mod tests {
 
         let cfg = load_config();
 
-        assert_eq!(cfg.watch_dir, "/mnt/smb");
-        assert_eq!(cfg.is_smb, false);
+        assert_eq!(cfg.watch_dir, "/mnt/smb/Test-transcoding");
+        assert!(!cfg.is_smb);
         assert!(cfg.threads >= 1);
     }
 
@@ -46,7 +288,7 @@ mod tests {
         let cfg = load_config();
 
         assert_eq!(cfg.watch_dir, "/custom/dir");
-        assert_eq!(cfg.is_smb, true);
+        assert!(cfg.is_smb);
         assert_eq!(cfg.threads, 4);
 
         // Clean up to avoid affecting other tests
@@ -65,6 +307,88 @@ mod tests {
         env::remove_var("THREADS");
     }
 
+    #[test]
+    fn test_log_settings() {
+        env::set_var("LOG_DIR", "/custom/logs");
+        env::set_var("LOG_RETENTION_DAYS", "3");
+
+        let cfg = load_config();
+        assert_eq!(cfg.log_dir, "/custom/logs");
+        assert_eq!(cfg.log_retention_days, 3);
+
+        env::set_var("LOG_RETENTION_DAYS", "soon");
+        assert_eq!(load_config().log_retention_days, 14);
+
+        env::remove_var("LOG_DIR");
+        env::remove_var("LOG_RETENTION_DAYS");
+    }
+
+    #[test]
+    fn test_webhook_urls() {
+        env::set_var("WEBHOOK_URLS", "http://a.example/hook, ,https://b.example/hook");
+        assert_eq!(
+            load_config().webhook_urls,
+            vec!["http://a.example/hook".to_string(), "https://b.example/hook".to_string()]
+        );
+
+        env::remove_var("WEBHOOK_URLS");
+        assert!(load_config().webhook_urls.is_empty());
+    }
+
+    #[test]
+    fn test_timeout_settings() {
+        env::set_var("TIMEOUT_FACTOR", "2.5");
+        env::set_var("STALL_SECS", "60");
+        let cfg = load_config();
+        assert_eq!((cfg.timeout_factor, cfg.stall_secs), (2.5, 60));
+
+        env::set_var("TIMEOUT_FACTOR", "-1");
+        assert_eq!(load_config().timeout_factor, 4.0);
+        env::set_var("TIMEOUT_FACTOR", "inf");
+        assert_eq!(load_config().timeout_factor, 4.0);
+
+        env::remove_var("TIMEOUT_FACTOR");
+        env::remove_var("STALL_SECS");
+    }
+
+    #[test]
+    fn test_retry_settings() {
+        env::set_var("MAX_ATTEMPTS", "0");
+        env::set_var("RETRY_BASE_SECS", "60");
+        let cfg = load_config();
+        assert_eq!((cfg.max_attempts, cfg.retry_base_secs), (5, 60));
+
+        env::remove_var("MAX_ATTEMPTS");
+        env::remove_var("RETRY_BASE_SECS");
+    }
+
+    #[test]
+    fn test_resource_settings() {
+        env::set_var("FFMPEG_NICE", "10");
+        env::set_var("FFMPEG_THREADS", "0");
+        env::set_var("CGROUP_CPUS", "2.5");
+        let cfg = load_config();
+        assert_eq!((cfg.ffmpeg_nice, cfg.ffmpeg_threads, cfg.cgroup_cpus), (Some(10), None, Some(2.5)));
+
+        env::set_var("FFMPEG_NICE", "40");
+        assert_eq!(load_config().ffmpeg_nice, None);
+
+        env::remove_var("FFMPEG_NICE");
+        env::remove_var("FFMPEG_THREADS");
+        env::remove_var("CGROUP_CPUS");
+    }
+
+    #[test]
+    fn test_stable_secs() {
+        env::set_var("STABLE_SECS", "0");
+        assert_eq!(load_config().stable_secs, 0);
+
+        env::set_var("STABLE_SECS", "a while");
+        assert_eq!(load_config().stable_secs, 15);
+
+        env::remove_var("STABLE_SECS");
+    }
+
     #[test]
     fn test_is_smb_case_insensitive() {
         env::set_var("IS_SMB", "TrUe");
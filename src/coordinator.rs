@@ -0,0 +1,399 @@
+use crate::disposal;
+use crate::hooks;
+use crate::ledger::clear_interrupted;
+use crate::probe::probe;
+use crate::processing::{record_error, record_success, Queue, QueuedJob};
+use crate::publish::partial_path;
+use crate::thumbnails;
+use crate::transcode::{JobAction, JobOutcome};
+use crate::verify::{verify_output, OutputExpectation};
+use crate::worker::parse_outcome;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Header carrying the worker's name on every request.
+pub const WORKER_HEADER: &str = "X-Worker";
+/// A job turned down by this many workers for a profile mismatch counts as failed.
+const MAX_MISMATCHES: usize = 3;
+
+/// A queued job handed to a remote worker.
+#[derive(Debug)]
+struct Assignment {
+    path: PathBuf,
+    job: QueuedJob,
+    worker: String,
+    started: Instant,
+    last_seen: Instant,
+}
+
+/// Owns the job queue for remote workers: hands out jobs over HTTP, serves their
+/// sources, takes their results and records outcomes in the local ledgers.
+///
+/// | Request                    | Meaning                                              |
+/// |----------------------------|------------------------------------------------------|
+/// | `POST /jobs/claim`         | next job as `key=value` lines, or 204 when idle      |
+/// | `GET /jobs/<id>/source`    | the source file (also `/subtitle`)                   |
+/// | `POST /jobs/<id>/heartbeat`| still working; 410 once the job was reassigned       |
+/// | `PUT /jobs/<id>/output`    | the finished MP4; verified, then published           |
+/// | `POST /jobs/<id>/done`     | finished on a shared path; `action=`, `duration=`    |
+/// | `POST /jobs/<id>/failed`   | failed, with the error as the body                   |
+/// | `POST /jobs/<id>/vetoed`   | refused by the job-started hook, reason as the body  |
+/// | `POST /jobs/<id>/release`  | not done now (deferred, interrupted); requeue        |
+/// | `POST /jobs/<id>/mismatch` | worker has another profile, details as the body      |
+#[derive(Debug)]
+pub struct Coordinator {
+    queue: Arc<Queue>,
+    assignments: Mutex<HashMap<u64, Assignment>>,
+    /// Workers that turned a source down for a profile mismatch; it isn't offered to them again.
+    mismatches: Mutex<HashMap<PathBuf, HashSet<String>>>,
+    next_id: AtomicU64,
+    /// Jobs whose worker hasn't been heard from for this long go back on the queue.
+    heartbeat_timeout: Duration,
+}
+
+impl Coordinator {
+    pub fn new(queue: Arc<Queue>, heartbeat_timeout: Duration) -> Self {
+        Coordinator {
+            queue,
+            assignments: Mutex::new(HashMap::new()),
+            mismatches: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            heartbeat_timeout,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Assignment>> {
+        self.assignments.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_mismatches(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, HashSet<String>>> {
+        self.mismatches.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Number of jobs currently out with workers.
+    pub fn assigned(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Answers worker requests from `server`, each on its own thread since
+    /// transfers can take a while. Returns once the server is unblocked.
+    pub fn serve(self: &Arc<Self>, server: Server) {
+        for request in server.incoming_requests() {
+            let coordinator = Arc::clone(self);
+            thread::spawn(move || coordinator.handle(request));
+        }
+    }
+
+    /// Periodically requeues the jobs of workers that stopped sending heartbeats.
+    pub fn start_reaper(self: &Arc<Self>) -> JoinHandle<()> {
+        let coordinator = Arc::clone(self);
+        thread::spawn(move || loop {
+            thread::sleep(coordinator.heartbeat_timeout / 3);
+            coordinator.reassign_stale(Instant::now());
+        })
+    }
+
+    /// Requeues (first in line) every job not heard about since `heartbeat_timeout`
+    /// before `now`. Returns how many were requeued.
+    pub fn reassign_stale(&self, now: Instant) -> usize {
+        let stale: Vec<Assignment> = {
+            let mut assignments = self.lock();
+            let ids: Vec<u64> = assignments
+                .iter()
+                .filter(|(_, a)| now.saturating_duration_since(a.last_seen) > self.heartbeat_timeout)
+                .map(|(id, _)| *id)
+                .collect();
+            ids.iter().filter_map(|id| assignments.remove(id)).collect()
+        };
+        for assignment in &stale {
+            println!(
+                "💔 Worker {} stopped responding, requeueing {}",
+                assignment.worker,
+                assignment.path.display()
+            );
+            self.queue.release(&assignment.path);
+            self.queue.push_front(assignment.path.clone(), assignment.job.clone());
+        }
+        stale.len()
+    }
+
+    fn handle(&self, mut request: Request) {
+        let worker = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv(WORKER_HEADER))
+            .map(|h| h.value.to_string())
+            .unwrap_or_else(|| "unknown".into());
+        let url = request.url().to_string();
+        let parts: Vec<&str> = url.trim_matches('/').split('/').collect();
+        let method = request.method().clone();
+
+        let result = match (&method, parts.as_slice()) {
+            (Method::Post, ["jobs", "claim"]) => return respond(request, self.claim(&worker)),
+            (_, ["jobs", id, action]) => match id.parse::<u64>() {
+                Ok(id) => match (&method, *action) {
+                    (Method::Get, "source" | "subtitle") => return self.send_file(request, id, action),
+                    (Method::Post, "heartbeat") => self.heartbeat(id),
+                    (Method::Put, "output") => self.receive_output(id, request.as_reader()),
+                    (Method::Post, "done") => {
+                        let mut body = String::new();
+                        let _ = request.as_reader().take(64 * 1024).read_to_string(&mut body);
+                        let (action, duration) = parse_outcome(&body);
+                        self.finish(id, |a| self.shared_output(a, action, duration))
+                    }
+                    (Method::Post, "failed") => {
+                        let mut error = String::new();
+                        let _ = request.as_reader().take(64 * 1024).read_to_string(&mut error);
+                        self.finish(id, |a| {
+                            record_error(&a.path, &a.job, error.trim());
+                            Ok(())
+                        })
+                    }
+                    (Method::Post, "vetoed") => {
+                        let mut reason = String::new();
+                        let _ = request.as_reader().take(64 * 1024).read_to_string(&mut reason);
+                        self.vetoed(id, reason.trim())
+                    }
+                    (Method::Post, "release") => self.release(id),
+                    (Method::Post, "mismatch") => {
+                        let mut reason = String::new();
+                        let _ = request.as_reader().take(64 * 1024).read_to_string(&mut reason);
+                        self.mismatch(id, reason.trim())
+                    }
+                    _ => Err(405),
+                },
+                Err(_) => Err(404),
+            },
+            _ => Err(404),
+        };
+        let _ = request.respond(Response::empty(result.err().unwrap_or(200)));
+    }
+
+    fn claim(&self, worker: &str) -> Response<io::Cursor<Vec<u8>>> {
+        let turned_down: HashSet<PathBuf> = self
+            .lock_mismatches()
+            .iter()
+            .filter(|(_, workers)| workers.contains(worker))
+            .map(|(path, _)| path.clone())
+            .collect();
+        let Some((path, job)) = self.queue.take_where(|path| !turned_down.contains(path)) else {
+            return Response::from_data(Vec::new()).with_status_code(204);
+        };
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        println!("📤 Assigned {} to worker {}", path.display(), worker);
+        let body = describe(id, &path, &job);
+        let now = Instant::now();
+        self.lock().insert(
+            id,
+            Assignment { path, job, worker: worker.to_string(), started: now, last_seen: now },
+        );
+        Response::from_data(body.into_bytes())
+    }
+
+    fn send_file(&self, request: Request, id: u64, which: &str) {
+        let path = match self.lock().get(&id) {
+            Some(assignment) if which == "source" => Some(assignment.path.clone()),
+            Some(assignment) => assignment.job.subtitle.clone(),
+            None => {
+                let _ = request.respond(Response::empty(410));
+                return;
+            }
+        };
+        let _ = match path.map(File::open) {
+            Some(Ok(file)) => request.respond(Response::from_file(file)),
+            _ => request.respond(Response::empty(404)),
+        };
+    }
+
+    fn heartbeat(&self, id: u64) -> Result<(), u16> {
+        match self.lock().get_mut(&id) {
+            Some(assignment) => {
+                assignment.last_seen = Instant::now();
+                Ok(())
+            }
+            None => Err(410),
+        }
+    }
+
+    /// Streams an uploaded output beside its final name, checks it, then moves it
+    /// into place. An output that fails the checks is dropped and the job requeued.
+    fn receive_output(&self, id: u64, body: &mut dyn Read) -> Result<(), u16> {
+        let (source, output, profile) = match self.lock().get(&id) {
+            Some(assignment) => (
+                assignment.path.clone(),
+                assignment.path.with_extension("mp4"),
+                assignment.job.settings.options.profile.clone(),
+            ),
+            None => return Err(410),
+        };
+        // Per assignment, so a late upload for a reassigned job can't write into the current one
+        let partial = partial_path(&output).with_extension(format!("{}.tmp", id));
+        let written = File::create(&partial).and_then(|mut file| {
+            io::copy(body, &mut file)?;
+            file.sync_all()
+        });
+        if let Err(e) = written {
+            println!("⚠️ Failed to receive {}: {}", output.display(), e);
+            let _ = fs::remove_file(&partial);
+            return Err(500);
+        }
+
+        // A cut-off or mangled upload must not replace the source
+        let source_info = probe(&source).ok();
+        let mut verified = None;
+        self.finish(id, |assignment| {
+            let expected = OutputExpectation {
+                duration: source_info.as_ref().and_then(|info| info.duration),
+                video: 1,
+                audio: source_info.as_ref().map(|info| info.streams_of("audio").len()),
+                // The worker converts without a subtitle that has no usable cues
+                subtitle: if assignment.job.subtitle.is_some() { None } else { Some(0) },
+            };
+            let info = verify_output(&partial, &expected)
+                .map_err(|problems| io::Error::other(format!("upload failed verification: {}", problems.join("; "))))?;
+            fs::rename(&partial, &output)?;
+            // The worker only had a copy, so the source is handled here
+            let action = &assignment.job.settings.options.profile.source_action;
+            if let Err(e) = disposal::apply(action, &assignment.path, &output) {
+                println!("⚠️ Failed to handle source {}: {}", assignment.path.display(), e);
+            }
+            let duration = source_info.as_ref().and_then(|info| info.duration);
+            record_success(&assignment.job, &outcome(assignment, &output, JobAction::Encoded, duration));
+            verified = Some(info);
+            Ok(())
+        })
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&partial);
+        })?;
+
+        // Workers skip thumbnails when uploading, since the files would stay behind with them
+        let duration = verified.and_then(|info| info.duration);
+        thumbnails::generate(&profile, &output, duration);
+        Ok(())
+    }
+
+    /// A worker on shared storage has written the output itself (or found it there).
+    fn shared_output(&self, assignment: &Assignment, action: JobAction, duration: Option<f64>) -> io::Result<()> {
+        let output = assignment.path.with_extension("mp4");
+        if !output.exists() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "reported output is missing"));
+        }
+        record_success(&assignment.job, &outcome(assignment, &output, action, duration));
+        Ok(())
+    }
+
+    /// Ends assignment `id` with `f`; on error the job is requeued.
+    fn finish(
+        &self,
+        id: u64,
+        f: impl FnOnce(&Assignment) -> io::Result<()>,
+    ) -> Result<(), u16> {
+        let Some(assignment) = self.lock().remove(&id) else {
+            return Err(410);
+        };
+        let result = f(&assignment);
+        self.lock_mismatches().remove(&assignment.path);
+        self.queue.release(&assignment.path);
+        if let Err(e) = result {
+            println!("⚠️ Could not accept result for {}: {}", assignment.path.display(), e);
+            self.queue.push(assignment.path, assignment.job);
+            return Err(500);
+        }
+        if assignment.job.resume {
+            clear_interrupted(&assignment.path.file_stem().unwrap_or_default().to_string_lossy());
+        }
+        Ok(())
+    }
+
+    fn release(&self, id: u64) -> Result<(), u16> {
+        let Some(assignment) = self.lock().remove(&id) else {
+            return Err(410);
+        };
+        println!("↩️ Worker {} handed back {}", assignment.worker, assignment.path.display());
+        self.queue.release(&assignment.path);
+        self.queue.push(assignment.path, assignment.job);
+        Ok(())
+    }
+
+    /// Keeps the job away from a worker with another profile. Once enough workers
+    /// have turned it down, the profiles are out of step and the job fails.
+    fn mismatch(&self, id: u64, reason: &str) -> Result<(), u16> {
+        let Some(assignment) = self.lock().remove(&id) else {
+            return Err(410);
+        };
+        println!("🧬 Worker {} can't encode {}: {}", assignment.worker, assignment.path.display(), reason);
+        let turned_down = {
+            let mut mismatches = self.lock_mismatches();
+            let workers = mismatches.entry(assignment.path.clone()).or_default();
+            workers.insert(assignment.worker.clone());
+            let count = workers.len();
+            if count >= MAX_MISMATCHES {
+                mismatches.remove(&assignment.path);
+            }
+            count
+        };
+        self.queue.release(&assignment.path);
+        if turned_down >= MAX_MISMATCHES {
+            let error = format!("profile mismatch on {} workers, last: {}", turned_down, reason);
+            record_error(&assignment.path, &assignment.job, &error);
+        } else {
+            self.queue.push(assignment.path, assignment.job);
+        }
+        Ok(())
+    }
+
+    /// Drops a vetoed job until its veto lapses; the next scan after that offers it again.
+    fn vetoed(&self, id: u64, reason: &str) -> Result<(), u16> {
+        let Some(assignment) = self.lock().remove(&id) else {
+            return Err(410);
+        };
+        println!("🚫 Not started {} on {}: {}", assignment.path.display(), assignment.worker, reason);
+        hooks::remember_veto(&assignment.path);
+        self.queue.release(&assignment.path);
+        Ok(())
+    }
+}
+
+fn respond(request: Request, response: Response<io::Cursor<Vec<u8>>>) {
+    let content_type = Header::from_bytes("Content-Type", "text/plain; charset=utf-8").ok();
+    let response = match content_type {
+        Some(header) => response.with_header(header),
+        None => response,
+    };
+    let _ = request.respond(response);
+}
+
+fn outcome(assignment: &Assignment, output: &Path, action: JobAction, media_duration: Option<f64>) -> JobOutcome {
+    JobOutcome {
+        action,
+        input: assignment.path.clone(),
+        output: output.to_path_buf(),
+        input_size: fs::metadata(&assignment.path).map(|m| m.len()).unwrap_or(0),
+        output_size: fs::metadata(output).map(|m| m.len()).unwrap_or(0),
+        media_duration,
+        elapsed: assignment.started.elapsed(),
+    }
+}
+
+/// The claim response: `key=value` lines a worker parses with `worker::parse_job`.
+/// The profile fingerprint lets a worker refuse a job it would encode differently.
+pub fn describe(id: u64, path: &Path, job: &QueuedJob) -> String {
+    let subtitle = job.subtitle.as_deref().map(|s| s.display().to_string()).unwrap_or_default();
+    let profile = &job.settings.options.profile;
+    format!(
+        "id={}\nsource={}\nsubtitle={}\noutput={}\nprofile={}\nprofile_fingerprint={}\n",
+        id,
+        path.display(),
+        subtitle,
+        path.with_extension("mp4").display(),
+        profile.name,
+        profile.fingerprint()
+    )
+}
@@ -0,0 +1,213 @@
+use chrono::{Local, NaiveDateTime, TimeZone};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+const TRASH_STAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+const DEFAULT_TRASH_RETENTION_DAYS: u64 = 30;
+
+/// What happens to a source once its output has been verified and published.
+/// Relative folders are resolved against the source's own folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceAction {
+    Keep,
+    Delete,
+    /// Move into this folder.
+    Archive(PathBuf),
+    /// Move into this folder, stamped, and purge entries older than `retention_days`.
+    Trash { dir: PathBuf, retention_days: u64 },
+    /// Let the output stand in for the source: it takes over the source's
+    /// permissions and modification time, and the source is removed.
+    Replace,
+}
+
+/// Parses `keep`, `delete`, `archive <dir>`, `trash <dir>` or `replace`.
+pub fn parse_source_action(value: &str) -> Result<SourceAction, String> {
+    let (keyword, rest) = value.split_once(char::is_whitespace).unwrap_or((value, ""));
+    let dir = rest.trim();
+    match (keyword.to_lowercase().as_str(), dir.is_empty()) {
+        ("keep", true) => Ok(SourceAction::Keep),
+        ("delete", true) => Ok(SourceAction::Delete),
+        ("replace", true) => Ok(SourceAction::Replace),
+        ("archive", false) => Ok(SourceAction::Archive(PathBuf::from(dir))),
+        ("trash", false) => Ok(SourceAction::Trash {
+            dir: PathBuf::from(dir),
+            retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+        }),
+        ("archive" | "trash", true) => Err(format!("{:?} needs a folder", keyword)),
+        _ => Err(format!("invalid source action {:?}", value)),
+    }
+}
+
+/// Applies `action` to `source`, whose verified output is `output`.
+/// Returns where the source ended up, or `None` if it no longer exists.
+pub fn apply(action: &SourceAction, source: &Path, output: &Path) -> io::Result<Option<PathBuf>> {
+    let base_dir = source.parent().unwrap_or(Path::new("."));
+    match action {
+        SourceAction::Keep => Ok(Some(source.to_path_buf())),
+        SourceAction::Delete => fs::remove_file(source).map(|_| None),
+        SourceAction::Archive(dir) => {
+            let dir = base_dir.join(dir);
+            fs::create_dir_all(&dir)?;
+            let mut target = dir.join(source.file_name().unwrap_or_default());
+            if target.exists() {
+                target = stamped_name(source, &dir);
+            }
+            move_file(source, &target).map(|_| Some(target))
+        }
+        SourceAction::Trash { dir, retention_days } => {
+            let dir = base_dir.join(dir);
+            fs::create_dir_all(&dir)?;
+            let target = stamped_name(source, &dir);
+            move_file(source, &target)?;
+            let purged = prune_trash(&dir, *retention_days);
+            if purged > 0 {
+                println!("🗑️ Purged {} trashed source(s) older than {} days", purged, retention_days);
+            }
+            Ok(Some(target))
+        }
+        SourceAction::Replace => {
+            let metadata = fs::metadata(source)?;
+            fs::set_permissions(output, metadata.permissions())?;
+            File::options().write(true).open(output)?.set_modified(metadata.modified()?)?;
+            fs::remove_file(source).map(|_| None)
+        }
+    }
+}
+
+/// True when `dir`, found while scanning for sources, is where `action` moves
+/// them, so its contents aren't picked up as new work. A relative folder is
+/// resolved against the folder it would sit in, which must hold videos (sources
+/// or their outputs); elsewhere a folder of that name is the user's own.
+pub fn is_disposal_dir(action: &SourceAction, dir: &Path) -> bool {
+    let target = match action {
+        SourceAction::Archive(target) | SourceAction::Trash { dir: target, .. } => target,
+        _ => return false,
+    };
+    if target.is_absolute() {
+        return dir.starts_with(target);
+    }
+    let depth = target.components().filter(|c| matches!(c, Component::Normal(_))).count();
+    let Some(base_dir) = dir.ancestors().nth(depth).filter(|_| depth > 0) else {
+        return false;
+    };
+    base_dir.join(target) == dir && holds_videos(base_dir)
+}
+
+fn holds_videos(dir: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+    entries
+        .filter_map(Result::ok)
+        .any(|entry| matches!(entry.path().extension().and_then(|e| e.to_str()), Some("mkv" | "mp4")))
+}
+
+/// `dir/<stem>.<timestamp>.<ext>`; the timestamp is what trash retention goes by,
+/// since a rename keeps the source's original mtime.
+fn stamped_name(source: &Path, dir: &Path) -> PathBuf {
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = source.extension().unwrap_or_default().to_string_lossy();
+    dir.join(format!("{}.{}.{}", stem, Local::now().format(TRASH_STAMP_FORMAT), extension))
+}
+
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    // Archive and trash folders may be on another filesystem than the watch folder
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Deletes trashed files whose stamp is older than `days`. Returns how many were removed.
+pub fn prune_trash(dir: &Path, days: u64) -> usize {
+    let max_age = Duration::from_secs(days.saturating_mul(24 * 60 * 60));
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            trashed_at(&name)
+                .and_then(|at| (Local::now() - at).to_std().ok())
+                .is_some_and(|age| age > max_age)
+        })
+        .filter(|entry| fs::remove_file(entry.path()).is_ok())
+        .count()
+}
+
+fn trashed_at(name: &str) -> Option<chrono::DateTime<Local>> {
+    let mut parts = name.rsplitn(3, '.');
+    let _extension = parts.next()?;
+    let stamp = parts.next()?;
+    let naive = NaiveDateTime::parse_from_str(stamp, TRASH_STAMP_FORMAT).ok()?;
+    Local.from_local_datetime(&naive).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("movie.mkv");
+        let output = dir.join("movie.mp4");
+        fs::write(&source, b"source").unwrap();
+        fs::write(&output, b"output").unwrap();
+        (dir, source, output)
+    }
+
+    #[test]
+    fn test_parse_source_action() {
+        assert_eq!(parse_source_action("keep"), Ok(SourceAction::Keep));
+        assert_eq!(parse_source_action("Replace"), Ok(SourceAction::Replace));
+        assert_eq!(
+            parse_source_action("archive /mnt/archive/tv shows"),
+            Ok(SourceAction::Archive(PathBuf::from("/mnt/archive/tv shows")))
+        );
+        assert!(parse_source_action("trash").is_err());
+        assert!(parse_source_action("shred").is_err());
+    }
+
+    #[test]
+    fn test_archive_and_trash_move_the_source() {
+        let (dir, source, output) = setup("test_disposal_archive");
+        let archived = apply(&SourceAction::Archive("archive".into()), &source, &output).unwrap();
+        assert_eq!(archived, Some(dir.join("archive").join("movie.mkv")));
+        assert!(!source.exists());
+
+        fs::write(&source, b"source").unwrap();
+        let old = dir.join("trash").join("old.20000101-000000.mkv");
+        fs::create_dir_all(old.parent().unwrap()).unwrap();
+        fs::write(&old, b"expired").unwrap();
+
+        let action = SourceAction::Trash { dir: "trash".into(), retention_days: 7 };
+        let trashed = apply(&action, &source, &output).unwrap().unwrap();
+        assert!(trashed.exists());
+        assert!(trashed_at(&trashed.file_name().unwrap().to_string_lossy()).is_some());
+        assert!(!old.exists());
+        // A retention too long to count in seconds keeps everything
+        assert_eq!(prune_trash(&dir.join("trash"), u64::MAX), 0);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_replace_carries_over_mtime() {
+        let (dir, source, output) = setup("test_disposal_replace");
+        let mtime = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        File::options().write(true).open(&source).unwrap().set_modified(mtime).unwrap();
+
+        assert_eq!(apply(&SourceAction::Replace, &source, &output).unwrap(), None);
+        assert!(!source.exists());
+        assert_eq!(fs::metadata(&output).unwrap().modified().unwrap(), mtime);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
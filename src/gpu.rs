@@ -2,6 +2,8 @@ use std::process::{Command};
 
 use std::fs;
 
+use crate::slots::Device;
+
 /// Detects GPU type based on Linux device files.
 ///
 /// Returns:
@@ -21,7 +23,7 @@ pub fn detect_gpu_from_devices() -> &'static str {
             let path = entry.path();
             if path.file_name()
                    .and_then(|n| n.to_str())
-                   .map_or(false, |name| name.starts_with("card") || name.starts_with("renderD")) {
+                   .is_some_and(|name| name.starts_with("card") || name.starts_with("renderD")) {
                 println!("🔌 VAAPI-compatible GPU detected: {:?}", path);
                 return "vaapi";
             }
@@ -48,6 +50,38 @@ pub fn detect_gpu_from_devices() -> &'static str {
     "cpu"
 }
 
+/// Every device of `backend`: VAAPI render nodes (`/dev/dri/renderD*`) or NVIDIA
+/// GPUs by CUDA index (from `/dev/nvidiaN`). Empty for CPU or when none are found.
+pub fn detect_devices(backend: &'static str) -> Vec<Device> {
+    let (dir, prefix) = match backend {
+        "vaapi" => ("/dev/dri", "renderD"),
+        "nvenc" => ("/dev", "nvidia"),
+        _ => return Vec::new(),
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut devices: Vec<Device> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let number = name.strip_prefix(prefix)?;
+            // nvidiactl, nvidia-uvm etc. aren't GPUs
+            if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let id = match backend {
+                "vaapi" => entry.path().display().to_string(),
+                _ => number.to_string(),
+            };
+            Some(Device { backend, id })
+        })
+        .collect();
+    devices.sort_by(|a, b| a.id.cmp(&b.id));
+    devices
+}
+
 //TODO: Work in progress
 pub fn detect_gpu_type() -> &'static str {
     if std::env::var("FORCE_CPU").is_ok() {
@@ -57,7 +91,7 @@ pub fn detect_gpu_type() -> &'static str {
 
     // Step 1: Try detecting NVIDIA GPU via CUDA init
     let nvidia = Command::new("ffmpeg")
-        .args(&[
+        .args([
             "-init_hw_device", "cuda=cu:0",
             "-f", "lavfi",
             "-i", "nullsrc",
@@ -78,7 +112,7 @@ pub fn detect_gpu_type() -> &'static str {
 
     // Step 2: Try detecting Intel/AMD via VAAPI
     let vaapi = Command::new("ffmpeg")
-                .args(&[
+                .args([
                     "-hwaccel", "vaapi",
                     "-f", "lavfi",
                     "-i", "nullsrc",
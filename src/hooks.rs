@@ -0,0 +1,308 @@
+use crate::watchdog::kill_process_group;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+pub const JOB_QUEUED: &str = "job-queued";
+pub const JOB_STARTED: &str = "job-started";
+pub const JOB_SUCCEEDED: &str = "job-succeeded";
+pub const JOB_FAILED: &str = "job-failed";
+
+const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_VETO_RECHECK: Duration = Duration::from_secs(600);
+
+/// User scripts run at job lifecycle events. A hook is an executable named after
+/// its event (e.g. `job-succeeded`) in `dir`; missing hooks are simply not run.
+///
+/// `job-queued` and `job-started` are pre-hooks: a non-zero exit (or a timeout)
+/// vetoes the job. Failures of the other hooks are only reported.
+///
+/// A vetoed source isn't offered to the pre-hooks again on every scan: the veto
+/// stands until the file changes or `veto_recheck` has passed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hooks {
+    pub dir: Option<PathBuf>,
+    pub timeout: Duration,
+    pub veto_recheck: Duration,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Hooks { dir: None, timeout: DEFAULT_HOOK_TIMEOUT, veto_recheck: DEFAULT_VETO_RECHECK }
+    }
+}
+
+/// A source as it was when a pre-hook refused it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Veto {
+    size: u64,
+    modified: Option<SystemTime>,
+    at: Instant,
+}
+
+static VETOED: Mutex<Option<HashMap<PathBuf, Veto>>> = Mutex::new(None);
+
+fn file_state(path: &Path) -> (u64, Option<SystemTime>) {
+    fs::metadata(path).map_or((0, None), |m| (m.len(), m.modified().ok()))
+}
+
+/// Remembers that a pre-hook refused `input` as it is now.
+pub fn remember_veto(input: &Path) {
+    let (size, modified) = file_state(input);
+    let mut guard = VETOED.lock().unwrap_or_else(|e| e.into_inner());
+    guard
+        .get_or_insert_with(HashMap::new)
+        .insert(input.to_path_buf(), Veto { size, modified, at: Instant::now() });
+}
+
+/// Job details handed to a hook, as `TRANSCODE_*` env vars and as JSON on stdin.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HookEvent {
+    pub event: &'static str,
+    pub input: PathBuf,
+    pub output: Option<PathBuf>,
+    pub profile: String,
+    /// Media duration of the source in seconds.
+    pub duration: Option<f64>,
+    pub input_size: Option<u64>,
+    pub output_size: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl HookEvent {
+    fn fields(&self) -> Vec<(&'static str, Option<String>, bool)> {
+        // (name, value, is_string)
+        vec![
+            ("event", Some(self.event.to_string()), true),
+            ("input", Some(self.input.display().to_string()), true),
+            ("output", self.output.as_ref().map(|p| p.display().to_string()), true),
+            ("profile", Some(self.profile.clone()), true),
+            ("duration", self.duration.map(|d| format!("{:.3}", d)), false),
+            ("input_size", self.input_size.map(|s| s.to_string()), false),
+            ("output_size", self.output_size.map(|s| s.to_string()), false),
+            ("error", self.error.clone(), true),
+        ]
+    }
+
+    /// `TRANSCODE_EVENT`, `TRANSCODE_INPUT`, ... for every field that is set.
+    pub fn env_vars(&self) -> Vec<(String, String)> {
+        self.fields()
+            .into_iter()
+            .filter_map(|(name, value, _)| Some((format!("TRANSCODE_{}", name.to_uppercase()), value?)))
+            .collect()
+    }
+
+    /// One JSON object; unset fields are `null`.
+    pub fn to_json(&self) -> String {
+        let fields: Vec<String> = self
+            .fields()
+            .into_iter()
+            .map(|(name, value, is_string)| {
+                let value = match value {
+                    None => "null".to_string(),
+                    Some(v) if is_string => json_string(&v),
+                    Some(v) => v,
+                };
+                format!("{}:{}", json_string(name), value)
+            })
+            .collect();
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl Hooks {
+    /// Runs the hook for `event.event`, if there is one. `Err` carries the reason
+    /// it failed; for pre-hooks that is a veto.
+    pub fn run(&self, event: &HookEvent) -> Result<(), String> {
+        let Some(path) = self.dir.as_ref().map(|dir| dir.join(event.event)) else {
+            return Ok(());
+        };
+        if !path.is_file() {
+            return Ok(());
+        }
+
+        let mut child = Command::new(&path)
+            .envs(event.env_vars())
+            .stdin(Stdio::piped())
+            // Own process group, so a timeout also takes down anything the hook started
+            .process_group(0)
+            .spawn()
+            .map_err(|e| format!("{} hook could not be started: {}", event.event, e))?;
+
+        // A hook that never reads stdin must not block us past the timeout
+        if let Some(mut stdin) = child.stdin.take() {
+            let json = event.to_json();
+            thread::spawn(move || {
+                let _ = stdin.write_all(json.as_bytes());
+            });
+        }
+
+        match wait_with_timeout(&mut child, self.timeout) {
+            Some(status) if status.success() => Ok(()),
+            Some(status) => Err(format!("{} hook exited with {}", event.event, status)),
+            None => Err(format!("{} hook timed out after {:?}", event.event, self.timeout)),
+        }
+    }
+
+    /// True while an earlier veto of `input` still stands: it is younger than
+    /// `veto_recheck` and the file hasn't changed since.
+    pub fn is_vetoed(&self, input: &Path) -> bool {
+        let mut guard = VETOED.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(vetoed) = guard.as_mut() else {
+            return false;
+        };
+        let Some(veto) = vetoed.get(input) else {
+            return false;
+        };
+        if veto.at.elapsed() < self.veto_recheck && (veto.size, veto.modified) == file_state(input) {
+            return true;
+        }
+        vetoed.remove(input);
+        false
+    }
+
+    /// Runs a post-hook, reporting rather than returning any failure.
+    pub fn notify(&self, event: &HookEvent) {
+        if let Err(e) = self.run(event) {
+            println!("⚠️ {}", e);
+        }
+    }
+}
+
+/// Waits for `child`, killing its whole process group once `timeout` has passed.
+/// Returns `None` when it had to be killed.
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(50)),
+            _ => break,
+        }
+    }
+    kill_process_group(child);
+    None
+}
+
+/// Hooks configured through `HOOK_DIR`, `HOOK_TIMEOUT_SECS` and `HOOK_VETO_RECHECK_SECS`.
+pub fn configured_hooks(dir: Option<&str>, timeout_secs: u64, veto_recheck_secs: u64) -> Hooks {
+    Hooks {
+        dir: dir.map(Path::new).map(Path::to_path_buf),
+        timeout: Duration::from_secs(timeout_secs),
+        veto_recheck: Duration::from_secs(veto_recheck_secs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn hook_dir(name: &str, hooks: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (event, script) in hooks {
+            let path = dir.join(event);
+            fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        dir
+    }
+
+    fn event(name: &'static str) -> HookEvent {
+        HookEvent {
+            event: name,
+            input: PathBuf::from("/shows/Pilot \"final\".mkv"),
+            profile: "default".into(),
+            input_size: Some(1024),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_hook_receives_env_and_json() {
+        let dir = hook_dir(
+            "test_hooks_payload",
+            &[(JOB_SUCCEEDED, "cat > \"$(dirname \"$0\")/stdin.json\"; echo \"$TRANSCODE_INPUT_SIZE $TRANSCODE_EVENT\" > \"$(dirname \"$0\")/env.txt\"")],
+        );
+        let hooks = Hooks { dir: Some(dir.clone()), ..Hooks::default() };
+
+        assert_eq!(hooks.run(&event(JOB_SUCCEEDED)), Ok(()));
+        assert_eq!(fs::read_to_string(dir.join("env.txt")).unwrap(), "1024 job-succeeded\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("stdin.json")).unwrap(),
+            r#"{"event":"job-succeeded","input":"/shows/Pilot \"final\".mkv","output":null,"profile":"default","duration":null,"input_size":1024,"output_size":null,"error":null}"#
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_failing_pre_hook_vetoes_and_missing_hook_allows() {
+        let dir = hook_dir("test_hooks_veto", &[(JOB_QUEUED, "exit 3")]);
+        let hooks = Hooks { dir: Some(dir.clone()), ..Hooks::default() };
+
+        let err = hooks.run(&event(JOB_QUEUED)).unwrap_err();
+        assert!(err.starts_with("job-queued hook exited with"), "{}", err);
+        assert_eq!(hooks.run(&event(JOB_STARTED)), Ok(()));
+        assert_eq!(Hooks::default().run(&event(JOB_QUEUED)), Ok(()));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_slow_hook_is_killed() {
+        let dir = hook_dir("test_hooks_timeout", &[(JOB_STARTED, "sleep 30")]);
+        let hooks = Hooks { dir: Some(dir.clone()), timeout: Duration::from_millis(200), ..Hooks::default() };
+
+        let start = Instant::now();
+        let err = hooks.run(&event(JOB_STARTED)).unwrap_err();
+        assert!(err.contains("timed out"), "{}", err);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_veto_stands_until_the_file_changes_or_expires() {
+        let path = std::env::temp_dir().join("test_hooks_vetoed.mkv");
+        fs::write(&path, b"episode").unwrap();
+        let hooks = Hooks::default();
+        assert!(!hooks.is_vetoed(&path));
+
+        remember_veto(&path);
+        assert!(hooks.is_vetoed(&path));
+        assert!(!Hooks { veto_recheck: Duration::ZERO, ..Hooks::default() }.is_vetoed(&path));
+
+        remember_veto(&path);
+        fs::write(&path, b"episode, remastered").unwrap();
+        assert!(!hooks.is_vetoed(&path));
+        let _ = fs::remove_file(&path);
+    }
+}
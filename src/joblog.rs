@@ -0,0 +1,187 @@
+use chrono::Local;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+/// Old logs are looked for this often while watching.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Per-job log file in the configured log directory, named after the source stem.
+///
+/// Each run of a job appends to the same file, separated by a header line, so a
+/// file that keeps failing has its whole history in one place until it's pruned.
+#[derive(Debug, Clone)]
+pub struct JobLog {
+    path: PathBuf,
+}
+
+impl JobLog {
+    pub fn new(log_dir: &Path, stem: &str) -> Self {
+        if let Err(e) = fs::create_dir_all(log_dir) {
+            println!("⚠️ Failed to create log dir {}: {}", log_dir.display(), e);
+        }
+        JobLog {
+            path: log_dir.join(format!("{}.log", stem)),
+        }
+    }
+
+    /// A log at exactly `path`, for logs that aren't about one job.
+    pub fn at(path: &Path) -> Self {
+        if let Some(dir) = path.parent() {
+            if let Err(e) = fs::create_dir_all(dir) {
+                println!("⚠️ Failed to create log dir {}: {}", dir.display(), e);
+            }
+        }
+        JobLog { path: path.to_path_buf() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn start(&self, input: &Path) {
+        self.write_raw(&format!(
+            "\n===== {} job started: {} =====\n",
+            Local::now().format("%Y-%m-%d %H:%M:%S"),
+            input.display()
+        ));
+    }
+
+    pub fn line(&self, message: &str) {
+        self.write_raw(&format!(
+            "[{}] {}\n",
+            Local::now().format("%Y-%m-%d %H:%M:%S"),
+            message
+        ));
+    }
+
+    pub fn command(&self, command: &Command) {
+        let args: Vec<_> = command.get_args().map(|a| a.to_string_lossy()).collect();
+        self.line(&format!(
+            "command: {} {}",
+            command.get_program().to_string_lossy(),
+            args.join(" ")
+        ));
+    }
+
+    /// Copies a child's stderr into the log line by line until it closes. Lines
+    /// that aren't UTF-8 are copied lossily, so the pipe is always drained.
+    pub fn capture(&self, output: impl Read) {
+        let mut file = match self.open() {
+            Ok(file) => file,
+            Err(e) => {
+                println!("⚠️ Failed to open log {}: {}", self.path.display(), e);
+                return;
+            }
+        };
+        for line in BufReader::new(output).split(b'\n').map_while(Result::ok) {
+            let _ = writeln!(file, "{}", String::from_utf8_lossy(&line).trim_end_matches('\r'));
+        }
+    }
+
+    pub fn finish(&self, outcome: &str, elapsed: Duration) {
+        self.line(&format!("outcome: {} after {:.2?}", outcome, elapsed));
+    }
+
+    fn write_raw(&self, text: &str) {
+        if let Ok(mut file) = self.open() {
+            let _ = file.write_all(text.as_bytes());
+        }
+    }
+
+    fn open(&self) -> io::Result<fs::File> {
+        OpenOptions::new().create(true).append(true).open(&self.path)
+    }
+}
+
+/// Prunes `log_dir` now and then every `PRUNE_INTERVAL`, on a background thread.
+pub fn start_pruning(log_dir: PathBuf, retention_days: u64) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        let pruned = prune_logs(&log_dir, retention_days);
+        if pruned > 0 {
+            println!("🧹 Pruned {} job log(s) older than {} days", pruned, retention_days);
+        }
+        thread::sleep(PRUNE_INTERVAL);
+    })
+}
+
+/// Deletes `.log` files in `log_dir` not written to for more than `retention_days`.
+/// A retention of 0 keeps logs forever. Returns how many files were removed.
+pub fn prune_logs(log_dir: &Path, retention_days: u64) -> usize {
+    if retention_days == 0 {
+        return 0;
+    }
+    let max_age = Duration::from_secs(retention_days * 24 * 60 * 60);
+    let Ok(entries) = fs::read_dir(log_dir) else {
+        return 0;
+    };
+
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("log") {
+            continue;
+        }
+        let age = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+        if age.is_some_and(|age| age > max_age) && fs::remove_file(&path).is_ok() {
+            removed += 1;
+        }
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_job_log_records_command_and_output() {
+        let dir = std::env::temp_dir().join("test_job_log_records");
+        let _ = fs::remove_dir_all(&dir);
+
+        let log = JobLog::new(&dir, "episode");
+        log.start(Path::new("/media/episode.mkv"));
+        let mut command = Command::new("ffmpeg");
+        command.arg("-i").arg("episode.mkv");
+        log.command(&command);
+        log.capture(&b"frame=1\nInput #0, title: caf\xe9\nerror: boom\n"[..]);
+        log.finish("failed", Duration::from_secs(3));
+
+        let text = fs::read_to_string(dir.join("episode.log")).unwrap();
+        assert!(text.contains("job started: /media/episode.mkv"));
+        assert!(text.contains("command: ffmpeg -i episode.mkv"));
+        assert!(text.contains("title: caf\u{fffd}\nerror: boom\n"));
+        assert!(text.contains("outcome: failed after 3.00s"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_prune_logs_removes_only_old_logs() {
+        let dir = std::env::temp_dir().join("test_prune_logs");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let old = File::create(dir.join("old.log")).unwrap();
+        old.set_modified(SystemTime::now() - Duration::from_secs(10 * 24 * 60 * 60)).unwrap();
+        File::create(dir.join("fresh.log")).unwrap();
+        let other = File::create(dir.join("old.txt")).unwrap();
+        other.set_modified(SystemTime::now() - Duration::from_secs(10 * 24 * 60 * 60)).unwrap();
+
+        assert_eq!(prune_logs(&dir, 0), 0);
+        assert_eq!(prune_logs(&dir, 7), 1);
+        assert!(!dir.join("old.log").exists());
+        assert!(dir.join("fresh.log").exists());
+        assert!(dir.join("old.txt").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
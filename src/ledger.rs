@@ -1,15 +1,30 @@
-use std::collections::HashSet;
+use chrono::{DateTime, Local, TimeZone};
+use std::collections::{HashMap, HashSet};
 use std::fs::{File, OpenOptions};
 use std::io::{BufRead, BufReader, Write};
+use std::sync::Mutex;
+use std::time::Duration;
 
 const LEDGER_PATH: &str = "/var/tmp/converted_ledger.txt";
+const FAILED_LEDGER_PATH: &str = "/var/tmp/failed_ledger.txt";
+const INTERRUPTED_LEDGER_PATH: &str = "/var/tmp/interrupted_ledger.txt";
 
 pub fn load_ledger() -> HashSet<String> {
-    if let Ok(file) = File::open(LEDGER_PATH) {
-        BufReader::new(file).lines().filter_map(Result::ok).collect()
-    } else {
-        HashSet::new()
-    }
+    read_lines(LEDGER_PATH).into_iter().collect()
+}
+
+/// Lines of a ledger file. A line that isn't valid UTF-8 is skipped rather than
+/// ending the read; only an I/O error stops it.
+fn read_lines(path: &str) -> Vec<String> {
+    let Ok(file) = File::open(path) else {
+        return Vec::new();
+    };
+    BufReader::new(file)
+        .split(b'\n')
+        .map_while(Result::ok)
+        .filter_map(|line| String::from_utf8(line).ok())
+        .map(|line| line.strip_suffix('\r').map(String::from).unwrap_or(line))
+        .collect()
 }
 
 pub fn append_to_ledger(entry: &str) {
@@ -32,6 +47,134 @@ pub fn save_ledger(ledger: &HashSet<String>) {
     }
 }
 
+/// Failure history of one entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailureRecord {
+    pub attempts: u32,
+    /// When the entry may be tried again; `None` once it has failed permanently.
+    pub next_retry: Option<DateTime<Local>>,
+    pub last_error: String,
+}
+
+impl FailureRecord {
+    pub fn is_permanent(&self) -> bool {
+        self.next_retry.is_none()
+    }
+
+    pub fn is_due(&self, now: DateTime<Local>) -> bool {
+        self.next_retry.is_some_and(|at| at <= now)
+    }
+}
+
+/// How often and how far apart failed entries are retried. The n-th retry waits
+/// `base_delay × 2^(n-1)`, capped at `max_delay`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn delay_after(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+// Failures are recorded from parallel jobs; serialize the read-modify-write
+static FAILED_LEDGER_LOCK: Mutex<()> = Mutex::new(());
+
+/// Failed entries. Lines are `entry<TAB>attempts<TAB>next retry (unix time, or - when
+/// permanent)<TAB>last error`; older `entry<TAB>reason` lines count as permanent.
+pub fn load_failed() -> HashMap<String, FailureRecord> {
+    read_lines(FAILED_LEDGER_PATH)
+        .iter()
+        .filter_map(|line| parse_failure(line))
+        .collect()
+}
+
+fn parse_failure(line: &str) -> Option<(String, FailureRecord)> {
+    let fields: Vec<&str> = line.splitn(4, '\t').collect();
+    let record = match fields[..] {
+        [_, attempts, next_retry, error] => FailureRecord {
+            attempts: attempts.parse().ok()?,
+            next_retry: match next_retry {
+                "-" => None,
+                secs => Some(Local.timestamp_opt(secs.parse().ok()?, 0).single()?),
+            },
+            last_error: error.to_string(),
+        },
+        [_, reason] => FailureRecord { attempts: 1, next_retry: None, last_error: reason.to_string() },
+        _ => return None,
+    };
+    Some((fields[0].to_string(), record))
+}
+
+fn save_failed(failed: &HashMap<String, FailureRecord>) {
+    if let Ok(mut file) = File::create(FAILED_LEDGER_PATH) {
+        for (entry, record) in failed {
+            let next_retry = record
+                .next_retry
+                .map(|at| at.timestamp().to_string())
+                .unwrap_or_else(|| "-".into());
+            let _ = writeln!(file, "{}\t{}\t{}\t{}", entry, record.attempts, next_retry, record.last_error);
+        }
+    }
+}
+
+/// Records another failed attempt for `entry` and schedules the next retry, or
+/// marks it permanently failed once `policy.max_attempts` is reached.
+pub fn record_failure(entry: &str, error: &str, policy: &RetryPolicy) -> FailureRecord {
+    let _guard = FAILED_LEDGER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut failed = load_failed();
+    let attempts = failed.get(entry).map_or(0, |r| r.attempts) + 1;
+    let next_retry = (attempts < policy.max_attempts).then(|| {
+        Local::now() + chrono::Duration::from_std(policy.delay_after(attempts)).unwrap_or(chrono::Duration::MAX)
+    });
+    let record = FailureRecord {
+        attempts,
+        next_retry,
+        last_error: error.replace(['\t', '\n'], " "),
+    };
+    failed.insert(entry.to_string(), record.clone());
+    save_failed(&failed);
+    record
+}
+
+/// Forgets the failures of `entry`. Returns whether there were any.
+pub fn clear_failed(entry: &str) -> bool {
+    let _guard = FAILED_LEDGER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut failed = load_failed();
+    let removed = failed.remove(entry).is_some();
+    if removed {
+        save_failed(&failed);
+    }
+    removed
+}
+
+/// Entries whose job was cut short by a shutdown, to be resumed first on the next start.
+pub fn load_interrupted() -> HashSet<String> {
+    read_lines(INTERRUPTED_LEDGER_PATH).into_iter().collect()
+}
+
+pub fn mark_interrupted(entry: &str) {
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(INTERRUPTED_LEDGER_PATH) {
+        let _ = writeln!(file, "{}", entry);
+    }
+}
+
+pub fn clear_interrupted(entry: &str) {
+    let mut interrupted = load_interrupted();
+    if interrupted.remove(entry) {
+        if let Ok(mut file) = File::create(INTERRUPTED_LEDGER_PATH) {
+            for entry in &interrupted {
+                let _ = writeln!(file, "{}", entry);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -52,9 +195,55 @@ mod tests {
 
         remove_from_ledger(entry);
         let ledger = load_ledger();
-        assert_eq!(ledger.contains(entry), false);
+        assert!(!ledger.contains(entry));
 
         // Cleanup after
         let _ = fs::remove_file(test_path);
     }
+
+    #[test]
+    fn test_failures_back_off_then_become_permanent() {
+        let entry = "test_failed_video";
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(60),
+            max_delay: Duration::from_secs(90),
+        };
+        clear_failed(entry);
+
+        let first = record_failure(entry, "ffmpeg exited with\tstatus 1", &policy);
+        assert_eq!((first.attempts, first.last_error.as_str()), (1, "ffmpeg exited with status 1"));
+        assert!(!first.is_due(Local::now()));
+        assert!(first.is_due(Local::now() + chrono::Duration::seconds(61)));
+
+        record_failure(entry, "timed out", &policy);
+        let third = record_failure(entry, "timed out again", &policy);
+        assert!(third.is_permanent());
+        assert_eq!(load_failed().get(entry), Some(&third));
+
+        assert!(clear_failed(entry));
+        assert!(!load_failed().contains_key(entry));
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_the_cap() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_secs(300),
+            max_delay: Duration::from_secs(3600),
+        };
+        let delays: Vec<u64> = (1..=5).map(|n| policy.delay_after(n).as_secs()).collect();
+        assert_eq!(delays, vec![300, 600, 1200, 2400, 3600]);
+
+        let (_, legacy) = parse_failure("old_video\tduration 3.00s differs").unwrap();
+        assert!(legacy.is_permanent());
+    }
+
+    #[test]
+    fn test_read_lines_skips_undecodable_lines() {
+        let path = std::env::temp_dir().join("test_ledger_read_lines.txt");
+        fs::write(&path, b"first\r\n\xff\xfe broken\nlast\n").unwrap();
+        assert_eq!(read_lines(path.to_str().unwrap()), vec!["first".to_string(), "last".to_string()]);
+        let _ = fs::remove_file(&path);
+    }
 }
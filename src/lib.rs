@@ -1,6 +1,33 @@
+pub mod chunked;
+pub mod coordinator;
+pub mod disposal;
 pub mod gpu;
+pub mod hooks;
+pub mod joblog;
 pub mod ledger;
+pub mod metadata;
+pub mod plan;
+pub mod preview;
+pub mod probe;
 pub mod processing;
+pub mod profile;
+pub mod progress;
+pub mod publish;
+pub mod queue;
+pub mod resources;
+pub mod shutdown;
+pub mod sidecar;
+pub mod slots;
+pub mod stability;
+pub mod subtitles;
+pub mod thumbnails;
+pub mod transcode;
+pub mod verify;
+pub mod watchdog;
 pub mod watcher;
+pub mod webhook;
+pub mod worker;
+pub mod workspace;
 pub mod config;
 pub mod app;
+pub mod cli;
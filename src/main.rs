@@ -1,3 +1,4 @@
 fn main() {
-    video_transcoder::app::start_transcoding_app();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(video_transcoder::cli::run(&args));
 }
@@ -0,0 +1,430 @@
+use crate::probe::MediaInfo;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Tags every MP4 carries regardless of policy; never treated as leftovers.
+const CONTAINER_TAGS: &[&str] = &["major_brand", "minor_version", "compatible_brands", "encoder"];
+
+/// Cover images looked up beside the source when attachments are rewritten without a path.
+const COVER_CANDIDATES: &[&str] = &["{stem}.jpg", "{stem}.png", "cover.jpg", "cover.png", "folder.jpg"];
+
+/// What to do with one kind of metadata. `Rewrite` carries its argument from the profile:
+///
+/// - chapters: title template, `{n}` is the chapter number (default `Chapter {n}`)
+/// - global_tags: `key=value` pairs separated by commas, `{stem}` is the file stem
+/// - stream_titles: title template, `{lang}` and `{type}` are substituted (default `{lang}`)
+/// - attachments: cover image path relative to the source folder (default: first of `COVER_CANDIDATES`)
+/// - encoder_tag: the encoder string to write
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Policy {
+    #[default]
+    Keep,
+    Strip,
+    Rewrite(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataPolicy {
+    pub chapters: Policy,
+    pub global_tags: Policy,
+    pub stream_titles: Policy,
+    pub attachments: Policy,
+    pub encoder_tag: Policy,
+}
+
+impl Default for MetadataPolicy {
+    fn default() -> Self {
+        MetadataPolicy {
+            chapters: Policy::Keep,
+            global_tags: Policy::Keep,
+            stream_titles: Policy::Keep,
+            // Cover art was never mapped before policies existed
+            attachments: Policy::Strip,
+            encoder_tag: Policy::Keep,
+        }
+    }
+}
+
+/// Parses `keep`, `strip` or `rewrite [argument]`.
+pub fn parse_policy(value: &str) -> Result<Policy, String> {
+    let (action, arg) = match value.split_once(char::is_whitespace) {
+        Some((action, arg)) => (action, arg.trim()),
+        None => (value, ""),
+    };
+    match action {
+        "keep" if arg.is_empty() => Ok(Policy::Keep),
+        "strip" if arg.is_empty() => Ok(Policy::Strip),
+        "rewrite" => Ok(Policy::Rewrite(arg.to_string())),
+        _ => Err(format!("invalid policy {:?} (expected keep, strip or rewrite ...)", value)),
+    }
+}
+
+/// Extra ffmpeg inputs a policy needs, added after the video and subtitle inputs.
+#[derive(Debug, Default)]
+pub struct MetadataInputs {
+    pub chapters: Option<PathBuf>,
+    pub cover: Option<PathBuf>,
+}
+
+impl MetadataInputs {
+    /// Input options and path of each extra input, in the order `output_args` maps them.
+    pub fn inputs(&self) -> Vec<(Vec<OsString>, PathBuf)> {
+        let mut inputs = Vec::new();
+        if let Some(chapters) = &self.chapters {
+            inputs.push((vec!["-f".into(), "ffmetadata".into()], chapters.clone()));
+        }
+        if let Some(cover) = &self.cover {
+            inputs.push((Vec::new(), cover.clone()));
+        }
+        inputs
+    }
+}
+
+/// Writes the rewritten chapter file and resolves the cover image, as the policy requires.
+pub fn prepare_inputs(
+    policy: &MetadataPolicy,
+    source: Option<&MediaInfo>,
+    input_file: &Path,
+    work_dir: &Path,
+) -> io::Result<MetadataInputs> {
+    let stem = file_stem(input_file);
+    let mut inputs = MetadataInputs::default();
+
+    if let (Policy::Rewrite(template), Some(source)) = (&policy.chapters, source) {
+        if !source.chapters.is_empty() {
+            let path = work_dir.join(format!("{}.chapters.txt", stem));
+            fs::write(&path, chapters_ffmetadata(source, template))?;
+            inputs.chapters = Some(path);
+        }
+    }
+
+    if let Policy::Rewrite(cover) = &policy.attachments {
+        let folder = input_file.parent().unwrap_or(Path::new("."));
+        let candidates: Vec<&str> = if cover.is_empty() { COVER_CANDIDATES.to_vec() } else { vec![cover] };
+        inputs.cover = candidates
+            .iter()
+            .map(|c| folder.join(c.replace("{stem}", &stem)))
+            .find(|p| p.is_file());
+        if inputs.cover.is_none() {
+            println!("⚠️ No cover image found for {}", input_file.display());
+        }
+    }
+
+    Ok(inputs)
+}
+
+fn chapters_ffmetadata(source: &MediaInfo, template: &str) -> String {
+    let template = if template.is_empty() { "Chapter {n}" } else { template };
+    let mut out = String::from(";FFMETADATA1\n");
+    for (n, chapter) in source.chapters.iter().enumerate() {
+        out.push_str(&format!(
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            (chapter.start * 1000.0).round() as u64,
+            (chapter.end * 1000.0).round() as u64,
+            escape_ffmetadata(&template.replace("{n}", &(n + 1).to_string()))
+        ));
+    }
+    out
+}
+
+fn escape_ffmetadata(value: &str) -> String {
+    value
+        .chars()
+        .flat_map(|c| match c {
+            '=' | ';' | '#' | '\\' | '\n' => vec!['\\', c],
+            _ => vec![c],
+        })
+        .collect()
+}
+
+/// Output options implementing the policy. `first_extra_input` is the ffmpeg input
+/// index of the first entry from `MetadataInputs::inputs`.
+pub fn output_args(
+    policy: &MetadataPolicy,
+    source: Option<&MediaInfo>,
+    inputs: &MetadataInputs,
+    first_extra_input: usize,
+    stem: &str,
+    has_subtitle: bool,
+) -> Vec<OsString> {
+    let mut args: Vec<String> = Vec::new();
+    let mut next_input = first_extra_input;
+
+    // Chapters
+    match (&policy.chapters, &inputs.chapters) {
+        (Policy::Rewrite(_), Some(_)) => {
+            args.extend(["-map_chapters".into(), next_input.to_string()]);
+            next_input += 1;
+        }
+        (Policy::Keep, _) => args.extend(["-map_chapters".into(), "0".into()]),
+        _ => args.extend(["-map_chapters".into(), "-1".into()]),
+    }
+
+    // Global tags
+    match &policy.global_tags {
+        Policy::Keep => args.extend(["-map_metadata:g".into(), "0".into()]),
+        Policy::Strip => args.extend(["-map_metadata:g".into(), "-1".into()]),
+        Policy::Rewrite(pairs) => {
+            args.extend(["-map_metadata:g".into(), "-1".into()]);
+            for (key, value) in tag_pairs(pairs, stem) {
+                args.extend(["-metadata".into(), format!("{}={}", key, value)]);
+            }
+        }
+    }
+
+    // Stream titles
+    match &policy.stream_titles {
+        Policy::Keep => {}
+        Policy::Strip => args.extend(["-metadata:s".into(), "title=".into()]),
+        Policy::Rewrite(template) => {
+            for (spec, title) in stream_titles(template, source, has_subtitle) {
+                args.extend([format!("-metadata:s:{}", spec), format!("title={}", title)]);
+            }
+        }
+    }
+
+    // Cover art (output video stream 0 is always the main picture)
+    match (&policy.attachments, source) {
+        (Policy::Keep, Some(source)) => {
+            for (n, cover) in source.cover_art().iter().enumerate() {
+                args.extend(["-map".into(), format!("0:{}", cover.index)]);
+                args.extend(cover_codec_args(n + 1));
+            }
+        }
+        (Policy::Rewrite(_), _) if inputs.cover.is_some() => {
+            args.extend(["-map".into(), format!("{}:v:0", next_input)]);
+            args.extend(cover_codec_args(1));
+        }
+        _ => {}
+    }
+
+    // Encoder tag
+    match &policy.encoder_tag {
+        Policy::Keep => {}
+        Policy::Strip => args.extend(["-fflags".into(), "+bitexact".into()]),
+        Policy::Rewrite(encoder) => args.extend(["-metadata".into(), format!("encoder={}", encoder)]),
+    }
+
+    args.into_iter().map(OsString::from).collect()
+}
+
+fn cover_codec_args(video_index: usize) -> [String; 4] {
+    [
+        format!("-c:v:{}", video_index),
+        "copy".into(),
+        format!("-disposition:v:{}", video_index),
+        "attached_pic".into(),
+    ]
+}
+
+fn tag_pairs(pairs: &str, stem: &str) -> Vec<(String, String)> {
+    pairs
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().replace("{stem}", stem)))
+        .collect()
+}
+
+/// `(stream specifier, title)` for every output stream.
+fn stream_titles(template: &str, source: Option<&MediaInfo>, has_subtitle: bool) -> Vec<(String, String)> {
+    let template = if template.is_empty() { "{lang}" } else { template };
+    let title = |kind: &str, lang: Option<&str>| {
+        template.replace("{lang}", lang.unwrap_or("und")).replace("{type}", kind)
+    };
+
+    let mut titles = Vec::new();
+    let video_lang = source.and_then(|s| s.streams_of("video").first().and_then(|v| v.language.clone()));
+    titles.push(("v:0".to_string(), title("Video", video_lang.as_deref())));
+    if let Some(source) = source {
+        for (i, audio) in source.streams_of("audio").iter().enumerate() {
+            titles.push((format!("a:{}", i), title("Audio", audio.language.as_deref())));
+        }
+    }
+    if has_subtitle {
+        titles.push(("s:0".to_string(), title("Subtitle", Some("eng"))));
+    }
+    titles
+}
+
+/// Compares a finished output against the policy. Returns one message per mismatch.
+pub fn verify(
+    policy: &MetadataPolicy,
+    source: &MediaInfo,
+    output: &MediaInfo,
+    inputs: &MetadataInputs,
+    stem: &str,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let expected_chapters = match &policy.chapters {
+        Policy::Keep | Policy::Rewrite(_) => source.chapters.len(),
+        Policy::Strip => 0,
+    };
+    if output.chapters.len() != expected_chapters {
+        problems.push(format!(
+            "chapters: expected {}, found {}",
+            expected_chapters,
+            output.chapters.len()
+        ));
+    }
+
+    let leftover_tags = || {
+        output
+            .tags
+            .keys()
+            .filter(|k| !CONTAINER_TAGS.contains(&k.as_str()))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    match &policy.global_tags {
+        Policy::Keep => {
+            if let Some(title) = source.tags.get("title") {
+                if output.tags.get("title") != Some(title) {
+                    problems.push(format!("global tags: title {:?} was not kept", title));
+                }
+            }
+        }
+        Policy::Strip => {
+            let leftover = leftover_tags();
+            if !leftover.is_empty() {
+                problems.push(format!("global tags: not stripped: {}", leftover.join(", ")));
+            }
+        }
+        Policy::Rewrite(pairs) => {
+            for (key, value) in tag_pairs(pairs, stem) {
+                if output.tags.get(&key) != Some(&value) {
+                    problems.push(format!("global tags: {} is not {:?}", key, value));
+                }
+            }
+        }
+    }
+
+    if policy.stream_titles == Policy::Strip {
+        if let Some(stream) = output.streams.iter().find(|s| s.title.as_deref().is_some_and(|t| !t.is_empty())) {
+            problems.push(format!("stream titles: stream {} still has a title", stream.index));
+        }
+    }
+
+    let expected_covers = match &policy.attachments {
+        Policy::Keep => source.cover_art().len(),
+        Policy::Strip => 0,
+        Policy::Rewrite(_) => usize::from(inputs.cover.is_some()),
+    };
+    if output.cover_art().len() != expected_covers {
+        problems.push(format!(
+            "attachments: expected {} cover image(s), found {}",
+            expected_covers,
+            output.cover_art().len()
+        ));
+    }
+
+    match &policy.encoder_tag {
+        Policy::Keep => {}
+        Policy::Strip => {
+            if let Some(encoder) = output.tags.get("encoder") {
+                problems.push(format!("encoder tag: not stripped ({})", encoder));
+            }
+        }
+        Policy::Rewrite(encoder) => {
+            if output.tags.get("encoder") != Some(encoder) {
+                problems.push(format!("encoder tag: expected {:?}", encoder));
+            }
+        }
+    }
+
+    problems
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::{Chapter, StreamInfo};
+
+    fn source() -> MediaInfo {
+        MediaInfo {
+            duration: Some(600.0),
+            streams: vec![
+                StreamInfo { index: 0, codec_type: "video".into(), ..Default::default() },
+                StreamInfo { index: 1, codec_type: "audio".into(), language: Some("jpn".into()), ..Default::default() },
+                StreamInfo { index: 2, codec_type: "video".into(), attached_pic: true, ..Default::default() },
+            ],
+            chapters: vec![
+                Chapter { start: 0.0, end: 90.5, title: Some("Intro".into()) },
+                Chapter { start: 90.5, end: 600.0, title: None },
+            ],
+            tags: [("title".to_string(), "Pilot".to_string())].into_iter().collect(),
+        }
+    }
+
+    fn strings(args: Vec<OsString>) -> Vec<String> {
+        args.into_iter().map(|a| a.into_string().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!(parse_policy("keep"), Ok(Policy::Keep));
+        assert_eq!(parse_policy("rewrite title={stem}"), Ok(Policy::Rewrite("title={stem}".into())));
+        assert!(parse_policy("strip everything").is_err());
+        assert!(parse_policy("drop").is_err());
+    }
+
+    #[test]
+    fn test_output_args_for_mixed_policy() {
+        let policy = MetadataPolicy {
+            chapters: Policy::Strip,
+            global_tags: Policy::Rewrite("title={stem}, comment=Transcoded".into()),
+            stream_titles: Policy::Rewrite("{type} ({lang})".into()),
+            attachments: Policy::Keep,
+            encoder_tag: Policy::Strip,
+        };
+        let args = strings(output_args(&policy, Some(&source()), &MetadataInputs::default(), 1, "Pilot", false));
+
+        assert_eq!(
+            args,
+            vec![
+                "-map_chapters", "-1",
+                "-map_metadata:g", "-1",
+                "-metadata", "title=Pilot",
+                "-metadata", "comment=Transcoded",
+                "-metadata:s:v:0", "title=Video (und)",
+                "-metadata:s:a:0", "title=Audio (jpn)",
+                "-map", "0:2", "-c:v:1", "copy", "-disposition:v:1", "attached_pic",
+                "-fflags", "+bitexact",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewritten_chapters_use_extra_input() {
+        let policy = MetadataPolicy { chapters: Policy::Rewrite(String::new()), ..Default::default() };
+        let inputs = MetadataInputs { chapters: Some("/tmp/x.chapters.txt".into()), cover: None };
+        let args = strings(output_args(&policy, Some(&source()), &inputs, 2, "x", true));
+        assert_eq!(&args[..2], ["-map_chapters", "2"]);
+
+        let meta = chapters_ffmetadata(&source(), "");
+        assert!(meta.contains("START=0\nEND=90500\ntitle=Chapter 1"));
+        assert!(meta.contains("START=90500\nEND=600000\ntitle=Chapter 2"));
+    }
+
+    #[test]
+    fn test_verify_reports_mismatches() {
+        let policy = MetadataPolicy { encoder_tag: Policy::Strip, ..Default::default() };
+        let mut output = source();
+        output.chapters.pop();
+        output.tags.insert("encoder".into(), "Lavf60.3.100".into());
+
+        let problems = verify(&policy, &source(), &output, &MetadataInputs::default(), "x");
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].starts_with("chapters"));
+        assert!(problems[1].starts_with("attachments"));
+        assert!(problems[2].starts_with("encoder tag"));
+    }
+}
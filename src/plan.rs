@@ -0,0 +1,484 @@
+use crate::metadata::{self, MetadataInputs};
+use crate::probe::MediaInfo;
+use crate::profile::Profile;
+use crate::verify::OutputExpectation;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const DEFAULT_VAAPI_DEVICE: &str = "/dev/dri/renderD128";
+
+/// A filter `(stream specifier, graph)` or codec `(option, value)` pair.
+type OptionPair = (String, String);
+
+/// One ffmpeg input: the options that precede its `-i`, and the path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanInput {
+    pub options: Vec<OsString>,
+    pub path: PathBuf,
+}
+
+/// A fully decided ffmpeg invocation. Building one has no side effects; turning
+/// it into a `Command` and running it is up to the caller.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FfmpegPlan {
+    pub inputs: Vec<PlanInput>,
+    /// `(stream specifier, filter graph)`, emitted as `-filter:<spec> <graph>`.
+    pub filters: Vec<(String, String)>,
+    pub maps: Vec<String>,
+    /// `(option, value)`, e.g. `("-c:v", "libx264")`.
+    pub codecs: Vec<(String, String)>,
+    pub output_options: Vec<OsString>,
+    pub output: PathBuf,
+    pub has_subtitle: bool,
+    /// What the output must look like for the job to count as a success.
+    pub expected: OutputExpectation,
+}
+
+impl FfmpegPlan {
+    pub fn builder<'a>(input: &Path, output: &Path, profile: &'a Profile) -> PlanBuilder<'a> {
+        PlanBuilder {
+            input: input.to_path_buf(),
+            output: output.to_path_buf(),
+            profile,
+            media: None,
+            gpu_type: "cpu",
+            device: None,
+            threads: None,
+            segment: None,
+            window: None,
+            video_from: None,
+            subtitle: None,
+            metadata_inputs: None,
+        }
+    }
+
+    /// Arguments after the program name, in the order ffmpeg expects them.
+    pub fn to_args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = ["-y", "-nostats", "-progress", "pipe:1"]
+            .into_iter()
+            .map(OsString::from)
+            .collect();
+
+        for input in &self.inputs {
+            args.extend(input.options.iter().cloned());
+            args.push("-i".into());
+            args.push(input.path.clone().into());
+        }
+        for (spec, graph) in &self.filters {
+            args.push(format!("-filter:{}", spec).into());
+            args.push(graph.into());
+        }
+        for map in &self.maps {
+            args.push("-map".into());
+            args.push(map.into());
+        }
+        for (option, value) in &self.codecs {
+            args.push(option.into());
+            args.push(value.into());
+        }
+        args.extend(self.output_options.iter().cloned());
+        args.push(self.output.clone().into());
+        args
+    }
+
+    pub fn command(&self) -> Command {
+        let mut command = Command::new("ffmpeg");
+        command.args(self.to_args());
+        command
+    }
+
+    /// The command line as a single string, for logs.
+    pub fn display(&self) -> String {
+        let args: Vec<_> = self.to_args().iter().map(|a| a.to_string_lossy().into_owned()).collect();
+        format!("ffmpeg {}", args.join(" "))
+    }
+}
+
+pub struct PlanBuilder<'a> {
+    input: PathBuf,
+    output: PathBuf,
+    profile: &'a Profile,
+    media: Option<&'a MediaInfo>,
+    gpu_type: &'static str,
+    device: Option<String>,
+    threads: Option<usize>,
+    segment: Option<(f64, f64)>,
+    window: Option<(f64, f64)>,
+    video_from: Option<PathBuf>,
+    subtitle: Option<PathBuf>,
+    metadata_inputs: Option<&'a MetadataInputs>,
+}
+
+impl<'a> PlanBuilder<'a> {
+    /// Probed source information. Without it, stream-dependent metadata
+    /// handling is skipped.
+    pub fn media(mut self, media: Option<&'a MediaInfo>) -> Self {
+        self.media = media;
+        self
+    }
+
+    /// "nvenc", "vaapi" or "cpu".
+    pub fn gpu(mut self, gpu_type: &'static str) -> Self {
+        self.gpu_type = gpu_type;
+        self
+    }
+
+    /// Which GPU to use: a VAAPI render node path or an NVENC CUDA index.
+    /// Defaults to `/dev/dri/renderD128` for VAAPI and the driver's choice for NVENC.
+    pub fn device(mut self, device: Option<&str>) -> Self {
+        self.device = device.map(String::from);
+        self
+    }
+
+    /// Encoder threads (`-threads`); by default ffmpeg uses every core.
+    pub fn threads(mut self, threads: Option<usize>) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Encode only the video between `start` and `end` seconds, with no audio,
+    /// subtitles or metadata: one piece of a chunked encode.
+    pub fn segment(mut self, segment: Option<(f64, f64)>) -> Self {
+        self.segment = segment;
+        self
+    }
+
+    /// Encode only `length` seconds from `start`, otherwise exactly as planned: a
+    /// preview sample. The subtitle is cut to match.
+    pub fn window(mut self, window: Option<(f64, f64)>) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Copy the video from the segments in this concat list instead of encoding
+    /// it; audio, subtitles and metadata still come from the input.
+    pub fn video_from(mut self, concat_list: Option<&Path>) -> Self {
+        self.video_from = concat_list.map(Path::to_path_buf);
+        self
+    }
+
+    /// A prepared (UTF-8, repaired) SRT file to mux as the first subtitle track.
+    pub fn subtitle(mut self, subtitle: Option<&Path>) -> Self {
+        self.subtitle = subtitle.map(Path::to_path_buf);
+        self
+    }
+
+    pub fn metadata_inputs(mut self, inputs: &'a MetadataInputs) -> Self {
+        self.metadata_inputs = Some(inputs);
+        self
+    }
+
+    pub fn build(self) -> FfmpegPlan {
+        if let Some((start, end)) = self.segment {
+            return self.build_segment(start, end);
+        }
+        // Video comes pre-encoded from a chunked encode, so the input isn't decoded for it
+        let video_copied = self.video_from.is_some();
+        let has_subtitle = self.subtitle.is_some();
+        let no_metadata_inputs = MetadataInputs::default();
+        let metadata_inputs = self.metadata_inputs.unwrap_or(&no_metadata_inputs);
+
+        // Step 1: Video input, with hardware decoding when encoding on the GPU
+        let decode_options = self.decode_options(video_copied);
+        let mut inputs = vec![PlanInput { options: decode_options, path: self.input.clone() }];
+
+        // Step 2: Subtitle input
+        if let Some(subtitle) = &self.subtitle {
+            inputs.push(PlanInput { options: vec!["-f".into(), "srt".into()], path: subtitle.clone() });
+        }
+
+        // Step 2a: Only a window of the source and subtitle, for previews
+        if let Some((start, length)) = self.window {
+            let cut: [OsString; 4] =
+                ["-ss".into(), format!("{:.3}", start).into(), "-t".into(), format!("{:.3}", length).into()];
+            for input in inputs.iter_mut() {
+                input.options.extend(cut.iter().cloned());
+            }
+        }
+
+        // Step 2b: Extra inputs required by the metadata policy (chapter file, cover image)
+        let first_extra_input = inputs.len();
+        inputs.extend(metadata_inputs.inputs().into_iter().map(|(options, path)| PlanInput { options, path }));
+
+        // Step 2c: Pre-encoded video segments, joined losslessly by the concat demuxer
+        let video_input = match &self.video_from {
+            Some(list) => {
+                inputs.push(PlanInput {
+                    options: vec!["-f".into(), "concat".into(), "-safe".into(), "0".into()],
+                    path: list.clone(),
+                });
+                inputs.len() - 1
+            }
+            None => 0,
+        };
+
+        // Step 3: Mapping
+        let mut maps = vec![format!("{}:v:0", video_input), "0:a?".to_string()];
+        if has_subtitle {
+            maps.push("1:s:0".into());
+        }
+
+        // Step 4: Video codec
+        let (filters, mut codecs) = self.video_codec(video_copied);
+
+        // Subtitle codec if present
+        if has_subtitle {
+            codecs.push(("-c:s".into(), "mov_text".into()));
+            codecs.push(("-metadata:s:s:0".into(), "language=eng".into()));
+        }
+
+        // Chapters, tags, stream titles, cover art and encoder tag
+        let stem = self.input.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut output_options = metadata::output_args(
+            &self.profile.metadata,
+            self.media,
+            metadata_inputs,
+            first_extra_input,
+            &stem,
+            has_subtitle,
+        );
+
+        // Audio and container options
+        let audio_and_container: &[&str] = match video_copied {
+            true => &["-c:a", "aac", "-b:a", "128k", "-movflags", "+faststart"],
+            false => &[
+                "-c:a", "aac", "-b:a", "128k", "-profile:v", "main", "-level:v", "4.0", "-movflags", "+faststart",
+            ],
+        };
+        output_options.extend(audio_and_container.iter().map(OsString::from));
+
+        let expected = OutputExpectation {
+            duration: self.window.map(|(_, length)| length).or(self.media.and_then(|m| m.duration)),
+            video: 1,
+            audio: self.media.map(|m| m.streams_of("audio").len()),
+            subtitle: Some(usize::from(has_subtitle)),
+        };
+
+        FfmpegPlan {
+            inputs,
+            filters,
+            maps,
+            codecs,
+            output_options,
+            output: self.output,
+            has_subtitle,
+            expected,
+        }
+    }
+
+    /// A video-only encode of `start..end` seconds of the input.
+    fn build_segment(self, start: f64, end: f64) -> FfmpegPlan {
+        let mut options = self.decode_options(false);
+        options.extend(["-ss".into(), format!("{:.3}", start).into(), "-t".into(), format!("{:.3}", end - start).into()]);
+        let (filters, codecs) = self.video_codec(false);
+        let output_options = [
+            "-an", "-sn", "-dn", "-map_metadata", "-1", "-map_chapters", "-1", "-profile:v", "main", "-level:v", "4.0",
+        ];
+        FfmpegPlan {
+            inputs: vec![PlanInput { options, path: self.input.clone() }],
+            filters,
+            maps: vec!["0:v:0".to_string()],
+            codecs,
+            output_options: output_options.iter().map(OsString::from).collect(),
+            output: self.output,
+            has_subtitle: false,
+            expected: OutputExpectation { duration: Some(end - start), video: 1, audio: Some(0), subtitle: Some(0) },
+        }
+    }
+
+    /// Hardware decoding options for the video input when encoding on the GPU.
+    fn decode_options(&self, video_copied: bool) -> Vec<OsString> {
+        match (self.gpu_type, video_copied) {
+            (_, true) => vec![],
+            ("nvenc", _) => {
+                let mut options: Vec<OsString> = vec!["-hwaccel".into(), "cuda".into()];
+                if let Some(device) = &self.device {
+                    options.extend(["-hwaccel_device".into(), device.into()]);
+                }
+                options
+            }
+            ("vaapi", _) => vec![
+                "-hwaccel".into(),
+                "vaapi".into(),
+                "-vaapi_device".into(),
+                self.device.as_deref().unwrap_or(DEFAULT_VAAPI_DEVICE).into(),
+            ],
+            _ => vec![],
+        }
+    }
+
+    /// Video filters and codec options for the selected encoder, or a plain copy.
+    fn video_codec(&self, video_copied: bool) -> (Vec<OptionPair>, Vec<OptionPair>) {
+        let mut filters = Vec::new();
+        let mut codecs = Vec::new();
+        let video_codec = match (self.gpu_type, video_copied) {
+            (_, true) => "copy",
+            ("nvenc", _) => "h264_nvenc",
+            ("vaapi", _) => {
+                filters.push(("v:0".to_string(), "format=nv12,hwupload".to_string()));
+                "h264_vaapi"
+            }
+            _ => "libx264",
+        };
+        codecs.push(("-c:v".to_string(), video_codec.to_string()));
+        if let ("h264_nvenc", Some(device)) = (video_codec, &self.device) {
+            codecs.push(("-gpu".to_string(), device.clone()));
+        }
+        if let (false, Some(threads)) = (video_copied, self.threads) {
+            codecs.push(("-threads".to_string(), threads.to_string()));
+        }
+        (filters, codecs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Policy;
+    use crate::probe::StreamInfo;
+
+    fn stream(codec_type: &str, codec_name: &str) -> StreamInfo {
+        StreamInfo {
+            codec_type: codec_type.into(),
+            codec_name: codec_name.into(),
+            ..Default::default()
+        }
+    }
+
+    fn args(plan: &FfmpegPlan) -> Vec<String> {
+        plan.to_args().into_iter().map(|a| a.into_string().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_cpu_plan_without_subtitle() {
+        let profile = Profile::default();
+        let plan = FfmpegPlan::builder(Path::new("/work/a.mkv"), Path::new("/media/a.mp4"), &profile).build();
+
+        assert_eq!(
+            args(&plan),
+            vec![
+                "-y", "-nostats", "-progress", "pipe:1",
+                "-i", "/work/a.mkv",
+                "-map", "0:v:0", "-map", "0:a?",
+                "-c:v", "libx264",
+                "-map_chapters", "0", "-map_metadata:g", "0",
+                "-c:a", "aac", "-b:a", "128k", "-profile:v", "main", "-level:v", "4.0",
+                "-movflags", "+faststart",
+                "/media/a.mp4",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_vaapi_plan_with_subtitle_and_chapters() {
+        let mut profile = Profile::default();
+        profile.metadata.chapters = Policy::Rewrite(String::new());
+        let metadata_inputs = MetadataInputs { chapters: Some("/work/a.chapters.txt".into()), cover: None };
+
+        let plan = FfmpegPlan::builder(Path::new("/work/a.mkv"), Path::new("/media/a.mp4"), &profile)
+            .gpu("vaapi")
+            .subtitle(Some(Path::new("/work/a.srt")))
+            .metadata_inputs(&metadata_inputs)
+            .build();
+
+        assert_eq!(
+            args(&plan),
+            vec![
+                "-y", "-nostats", "-progress", "pipe:1",
+                "-hwaccel", "vaapi", "-vaapi_device", "/dev/dri/renderD128", "-i", "/work/a.mkv",
+                "-f", "srt", "-i", "/work/a.srt",
+                "-f", "ffmetadata", "-i", "/work/a.chapters.txt",
+                "-filter:v:0", "format=nv12,hwupload",
+                "-map", "0:v:0", "-map", "0:a?", "-map", "1:s:0",
+                "-c:v", "h264_vaapi",
+                "-c:s", "mov_text", "-metadata:s:s:0", "language=eng",
+                "-map_chapters", "2", "-map_metadata:g", "0",
+                "-c:a", "aac", "-b:a", "128k", "-profile:v", "main", "-level:v", "4.0",
+                "-movflags", "+faststart",
+                "/media/a.mp4",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nvenc_plan_decodes_on_the_gpu() {
+        let profile = Profile::default();
+        let media = MediaInfo {
+            streams: vec![stream("video", "h264"), stream("audio", "aac")],
+            ..Default::default()
+        };
+
+        let plan = FfmpegPlan::builder(Path::new("/work/a.mkv"), Path::new("/media/a.mp4"), &profile)
+            .gpu("nvenc")
+            .media(Some(&media))
+            .build();
+        assert_eq!(args(&plan)[4..8], ["-hwaccel", "cuda", "-i", "/work/a.mkv"]);
+        assert_eq!(plan.codecs, vec![("-c:v".to_string(), "h264_nvenc".to_string())]);
+        assert_eq!(plan.expected, OutputExpectation { duration: None, video: 1, audio: Some(1), subtitle: Some(0) });
+    }
+
+    #[test]
+    fn test_plan_targets_selected_device() {
+        let profile = Profile::default();
+        let plan = FfmpegPlan::builder(Path::new("/work/a.mkv"), Path::new("/media/a.mp4"), &profile)
+            .gpu("nvenc")
+            .device(Some("1"))
+            .build();
+        assert_eq!(args(&plan)[4..10], ["-hwaccel", "cuda", "-hwaccel_device", "1", "-i", "/work/a.mkv"]);
+        assert_eq!(plan.codecs[1], ("-gpu".to_string(), "1".to_string()));
+
+        let plan = FfmpegPlan::builder(Path::new("/work/a.mkv"), Path::new("/media/a.mp4"), &profile)
+            .gpu("vaapi")
+            .device(Some("/dev/dri/renderD129"))
+            .build();
+        assert_eq!(args(&plan)[7], "/dev/dri/renderD129");
+    }
+
+    #[test]
+    fn test_plan_limits_encoder_threads() {
+        let profile = Profile::default();
+        let plan = FfmpegPlan::builder(Path::new("/work/a.mkv"), Path::new("/media/a.mp4"), &profile)
+            .threads(Some(4))
+            .build();
+        assert_eq!(plan.codecs[1], ("-threads".to_string(), "4".to_string()));
+    }
+
+    #[test]
+    fn test_chunked_plans() {
+        let profile = Profile::default();
+        let segment = FfmpegPlan::builder(Path::new("/work/a.mkv"), Path::new("/work/seg/0001.mkv"), &profile)
+            .gpu("vaapi")
+            .segment(Some((60.0, 120.5)))
+            .build();
+        assert_eq!(args(&segment)[8..14], ["-ss", "60.000", "-t", "60.500", "-i", "/work/a.mkv"]);
+        assert_eq!(segment.maps, vec!["0:v:0".to_string()]);
+        assert_eq!(segment.codecs[0], ("-c:v".to_string(), "h264_vaapi".to_string()));
+        assert_eq!(segment.expected.audio, Some(0));
+
+        let joined = FfmpegPlan::builder(Path::new("/work/a.mkv"), Path::new("/media/a.mp4"), &profile)
+            .gpu("vaapi")
+            .subtitle(Some(Path::new("/work/a.srt")))
+            .video_from(Some(Path::new("/work/seg/list.txt")))
+            .build();
+        assert!(joined.inputs[0].options.is_empty());
+        assert_eq!(joined.inputs[2].path, Path::new("/work/seg/list.txt"));
+        assert_eq!(joined.maps, vec!["2:v:0".to_string(), "0:a?".to_string(), "1:s:0".to_string()]);
+        assert_eq!(joined.codecs[0], ("-c:v".to_string(), "copy".to_string()));
+        assert!(joined.filters.is_empty());
+    }
+
+    #[test]
+    fn test_windowed_plan_cuts_source_and_subtitle() {
+        let profile = Profile::default();
+        let plan = FfmpegPlan::builder(Path::new("/work/a.mkv"), Path::new("/previews/a.1.mp4"), &profile)
+            .subtitle(Some(Path::new("/work/a.srt")))
+            .window(Some((300.0, 20.0)))
+            .build();
+        let cut = ["-ss", "300.000", "-t", "20.000"];
+        assert_eq!(args(&plan)[4..10], ["-ss", "300.000", "-t", "20.000", "-i", "/work/a.mkv"]);
+        assert_eq!(plan.inputs[1].options[2..], cut.map(OsString::from));
+        assert_eq!(plan.maps, vec!["0:v:0".to_string(), "0:a?".to_string(), "1:s:0".to_string()]);
+        assert_eq!(plan.expected.duration, Some(20.0));
+    }
+}
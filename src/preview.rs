@@ -0,0 +1,235 @@
+use crate::joblog::JobLog;
+use crate::metadata;
+use crate::plan::FfmpegPlan;
+use crate::probe::probe;
+use crate::profile::Profile;
+use crate::shutdown;
+use crate::transcode::{prepare_subtitle, run_ffmpeg, JobOptions};
+use crate::workspace::JobWorkspace;
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const APPROVALS_PATH: &str = "/var/tmp/approved_previews.txt";
+/// Folder, beside the source, that samples and reports are written to.
+pub const PREVIEW_DIR: &str = "previews";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreviewSettings {
+    pub samples: usize,
+    pub sample_secs: f64,
+}
+
+/// One encoded sample.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub start: f64,
+    pub length: f64,
+    pub size: u64,
+    pub encode_time: Duration,
+}
+
+/// What a profile would make of a whole file, judging by its samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreviewReport {
+    pub profile: String,
+    pub duration: f64,
+    pub source_size: u64,
+    pub samples: Vec<Sample>,
+}
+
+impl PreviewReport {
+    /// Output size of the whole file at the samples' average bitrate.
+    pub fn projected_size(&self) -> u64 {
+        let length: f64 = self.samples.iter().map(|s| s.length).sum();
+        let size: u64 = self.samples.iter().map(|s| s.size).sum();
+        if length <= 0.0 {
+            return 0;
+        }
+        (size as f64 / length * self.duration) as u64
+    }
+
+    /// Seconds of media encoded per second of wall-clock time.
+    pub fn speed(&self) -> f64 {
+        let length: f64 = self.samples.iter().map(|s| s.length).sum();
+        let time: f64 = self.samples.iter().map(|s| s.encode_time.as_secs_f64()).sum();
+        if time <= 0.0 {
+            return 0.0;
+        }
+        length / time
+    }
+
+    /// `key=value` lines, one `sample=` line per sample.
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "profile={}\nduration={:.3}\nsource_size={}\nprojected_size={}\nspeed={:.2}\n",
+            self.profile,
+            self.duration,
+            self.source_size,
+            self.projected_size(),
+            self.speed()
+        );
+        for sample in &self.samples {
+            text.push_str(&format!(
+                "sample={:.3}+{:.3} size={} encode_secs={:.2}\n",
+                sample.start,
+                sample.length,
+                sample.size,
+                sample.encode_time.as_secs_f64()
+            ));
+        }
+        text
+    }
+}
+
+/// Starts of `settings.samples` clips spread evenly through a video, each fully
+/// inside it. A video too short for that gets a single clip from the start.
+pub fn sample_starts(duration: f64, settings: &PreviewSettings) -> Vec<f64> {
+    let count = settings.samples.max(1);
+    if duration <= settings.sample_secs * count as f64 {
+        return vec![0.0];
+    }
+    let last_start = duration - settings.sample_secs;
+    (1..=count)
+        .map(|i| (duration * i as f64 / (count + 1) as f64 - settings.sample_secs / 2.0).clamp(0.0, last_start))
+        .collect()
+}
+
+/// Encodes samples of `input` with exactly the plan a real job would use, into
+/// `previews/` beside it, along with a `<stem>.<profile>.txt` report.
+pub fn preview_file(input: &Path, options: &JobOptions, settings: &PreviewSettings) -> Result<PreviewReport, String> {
+    let base = &*input.file_stem().unwrap_or_default().to_string_lossy();
+    let profile = &options.profile;
+    let start_time = Instant::now();
+
+    // Step 1: Probe; samples are read straight from the source
+    let source_size = fs::metadata(input).map_err(|e| e.to_string())?.len();
+    let info = probe(input).map_err(|e| e.to_string())?;
+    let duration = info.duration.ok_or("source duration is unknown")?;
+
+    // Step 2: Subtitle and metadata inputs, prepared as for the real job
+    fs::create_dir_all(&options.temp_dir).map_err(|e| e.to_string())?;
+    let workspace = JobWorkspace::create(&options.temp_dir, input).map_err(|e| e.to_string())?;
+    let job_log = JobLog::new(&options.log_dir, &format!("{}.preview", base));
+    job_log.start(input);
+    let subtitle = match options.subtitle.as_deref() {
+        Some(srt) => prepare_subtitle(input, srt, input, workspace.path(), &job_log).map_err(|e| e.to_string())?,
+        None => None,
+    };
+    let metadata_inputs = metadata::prepare_inputs(&profile.metadata, Some(&info), input, workspace.path())
+        .map_err(|e| e.to_string())?;
+
+    // Step 3: Encode each sample
+    let dir = input.parent().unwrap_or(Path::new(".")).join(PREVIEW_DIR);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let device = options.gpus.first().map(|d| d.id.as_str()).filter(|id| !id.is_empty());
+    let active = shutdown::register_job(input);
+    let mut samples = Vec::new();
+    for (i, start) in sample_starts(duration, settings).into_iter().enumerate() {
+        let length = settings.sample_secs.min(duration - start);
+        let output = dir.join(format!("{}.{}.{}.mp4", base, profile.name, i + 1));
+        let plan = FfmpegPlan::builder(input, &output, profile)
+            .gpu(options.gpu_type)
+            .device(device)
+            .threads(options.limits.threads)
+            .media(Some(&info))
+            .subtitle(subtitle.as_deref())
+            .metadata_inputs(&metadata_inputs)
+            .window(Some((start, length)))
+            .build();
+        let encode_start = Instant::now();
+        if let Err(e) = run_ffmpeg(&plan, base, Some(length), options, &job_log, &active) {
+            job_log.finish(&format!("preview failed: {}", e), start_time.elapsed());
+            return Err(e.to_string());
+        }
+        let size = fs::metadata(&output).map_err(|e| e.to_string())?.len();
+        samples.push(Sample { start, length, size, encode_time: encode_start.elapsed() });
+    }
+
+    // Step 4: Report
+    let report = PreviewReport { profile: profile.name.clone(), duration, source_size, samples };
+    fs::write(report_path(input, &profile.name), report.to_text()).map_err(|e| e.to_string())?;
+    job_log.finish("previewed", start_time.elapsed());
+    Ok(report)
+}
+
+/// True once previews of this profile, as it is now, have been approved; an
+/// edited profile has a new fingerprint and needs a new approval.
+pub fn is_approved(profile: &Profile) -> bool {
+    load_approvals(Path::new(APPROVALS_PATH)).contains(&approval_entry(profile))
+}
+
+pub fn approve(profile: &Profile) -> io::Result<()> {
+    approve_in(Path::new(APPROVALS_PATH), profile)
+}
+
+fn approval_entry(profile: &Profile) -> String {
+    format!("{} {}", profile.name, profile.fingerprint())
+}
+
+fn load_approvals(path: &Path) -> HashSet<String> {
+    match File::open(path) {
+        // A line that isn't UTF-8 is skipped rather than ending the read
+        Ok(file) => BufReader::new(file)
+            .split(b'\n')
+            .map_while(Result::ok)
+            .filter_map(|line| String::from_utf8(line).ok())
+            .collect(),
+        Err(_) => HashSet::new(),
+    }
+}
+
+fn approve_in(path: &Path, profile: &Profile) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", approval_entry(profile))
+}
+
+/// `<dir>/previews/<stem>.<profile>.txt` for a source in `dir`.
+pub fn report_path(input: &Path, profile: &str) -> PathBuf {
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    input.parent().unwrap_or(Path::new(".")).join(PREVIEW_DIR).join(format!("{}.{}.txt", stem, profile))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_starts() {
+        let settings = PreviewSettings { samples: 3, sample_secs: 20.0 };
+        assert_eq!(sample_starts(2400.0, &settings), vec![590.0, 1190.0, 1790.0]);
+        assert_eq!(sample_starts(50.0, &settings), vec![0.0]);
+        let one = PreviewSettings { samples: 1, sample_secs: 20.0 };
+        assert_eq!(sample_starts(100.0, &one), vec![40.0]);
+    }
+
+    #[test]
+    fn test_report_projection() {
+        let sample = |size, secs| Sample { start: 0.0, length: 20.0, size, encode_time: Duration::from_secs(secs) };
+        let report = PreviewReport {
+            profile: "tv".into(),
+            duration: 1200.0,
+            source_size: 1_000_000_000,
+            samples: vec![sample(4_000_000, 10), sample(6_000_000, 10)],
+        };
+        assert_eq!(report.projected_size(), 300_000_000);
+        assert_eq!(report.speed(), 2.0);
+        assert!(report.to_text().contains("projected_size=300000000\nspeed=2.00\n"));
+    }
+
+    #[test]
+    fn test_approval_follows_profile_settings() {
+        let path = std::env::temp_dir().join("test_preview_approvals.txt");
+        fs::write(&path, b"\xff\xfe garbled\n").unwrap();
+        let profile = Profile { name: "test_preview".into(), ..Profile::default() };
+        assert!(!load_approvals(&path).contains(&approval_entry(&profile)));
+
+        approve_in(&path, &profile).unwrap();
+        assert!(load_approvals(&path).contains(&approval_entry(&profile)));
+        let edited = Profile { cpu_overflow: true, ..profile };
+        assert!(!load_approvals(&path).contains(&approval_entry(&edited)));
+        let _ = fs::remove_file(&path);
+    }
+}
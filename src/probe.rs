@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamInfo {
+    pub index: usize,
+    /// "video", "audio", "subtitle", "attachment", ...
+    pub codec_type: String,
+    pub codec_name: String,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    /// Cover art is exposed by ffmpeg as a video stream with the attached_pic disposition.
+    pub attached_pic: bool,
+    /// Stream duration in seconds, from the stream itself or Matroska's DURATION tag.
+    pub duration: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chapter {
+    pub start: f64,
+    pub end: f64,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaInfo {
+    /// Container duration in seconds, when known.
+    pub duration: Option<f64>,
+    pub streams: Vec<StreamInfo>,
+    pub chapters: Vec<Chapter>,
+    /// Global (container) tags, keys lowercased.
+    pub tags: BTreeMap<String, String>,
+}
+
+impl MediaInfo {
+    /// Streams of a type, excluding cover art for "video".
+    pub fn streams_of(&self, codec_type: &str) -> Vec<&StreamInfo> {
+        self.streams
+            .iter()
+            .filter(|s| s.codec_type == codec_type && !s.attached_pic)
+            .collect()
+    }
+
+    pub fn cover_art(&self) -> Vec<&StreamInfo> {
+        self.streams.iter().filter(|s| s.attached_pic).collect()
+    }
+}
+
+/// Runs ffprobe on `path` and collects format, stream and chapter information.
+pub fn probe(path: &Path) -> io::Result<MediaInfo> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v", "error",
+            "-show_entries",
+            "format=duration:format_tags:stream=index,codec_type,codec_name,duration:stream_tags=language,title,duration:stream_disposition=attached_pic",
+            "-show_chapters",
+            "-of", "flat",
+        ])
+        .arg(path)
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "ffprobe failed on {}: {}",
+            path.display(),
+            stderr.trim()
+        )));
+    }
+
+    Ok(parse_flat(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses ffprobe's `-of flat` output, e.g. `streams.stream.0.codec_type="video"`.
+pub fn parse_flat(text: &str) -> MediaInfo {
+    let mut info = MediaInfo::default();
+    let mut streams: BTreeMap<usize, StreamInfo> = BTreeMap::new();
+    let mut chapters: BTreeMap<usize, Chapter> = BTreeMap::new();
+
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"').to_string();
+        let parts: Vec<&str> = key.split('.').collect();
+
+        match parts.as_slice() {
+            ["format", "duration"] => info.duration = value.parse().ok(),
+            ["format", "tags", tag] => {
+                info.tags.insert(tag.to_lowercase(), value);
+            }
+            ["streams", "stream", n, rest @ ..] => {
+                let Ok(n) = n.parse() else { continue };
+                let stream = streams.entry(n).or_default();
+                match rest {
+                    ["index"] => stream.index = value.parse().unwrap_or(n),
+                    ["codec_type"] => stream.codec_type = value,
+                    ["codec_name"] => stream.codec_name = value,
+                    ["disposition", "attached_pic"] => stream.attached_pic = value == "1",
+                    ["duration"] => stream.duration = value.parse().ok().or(stream.duration),
+                    ["tags", tag] if tag.eq_ignore_ascii_case("duration") => {
+                        stream.duration = stream.duration.or_else(|| parse_timestamp(&value));
+                    }
+                    ["tags", "language"] => stream.language = Some(value),
+                    ["tags", "title"] => stream.title = Some(value),
+                    _ => {}
+                }
+            }
+            ["chapters", "chapter", n, rest @ ..] => {
+                let Ok(n) = n.parse() else { continue };
+                let chapter = chapters.entry(n).or_default();
+                match rest {
+                    ["start_time"] => chapter.start = value.parse().unwrap_or_default(),
+                    ["end_time"] => chapter.end = value.parse().unwrap_or_default(),
+                    ["tags", "title"] => chapter.title = Some(value),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    info.streams = streams.into_values().collect();
+    info.chapters = chapters.into_values().collect();
+    info
+}
+
+/// Parses `HH:MM:SS.fraction`, as in Matroska DURATION tags.
+fn parse_timestamp(value: &str) -> Option<f64> {
+    let mut parts = value.split(':').rev();
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next().unwrap_or("0").parse().ok()?;
+    let hours: f64 = parts.next().unwrap_or("0").parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_flat_output() {
+        let text = r#"streams.stream.0.index=0
+streams.stream.0.codec_name="h264"
+streams.stream.0.codec_type="video"
+streams.stream.0.disposition.attached_pic=0
+streams.stream.1.index=1
+streams.stream.1.codec_name="aac"
+streams.stream.1.codec_type="audio"
+streams.stream.1.disposition.attached_pic=0
+streams.stream.1.tags.language="jpn"
+streams.stream.1.tags.title="Stereo"
+streams.stream.1.duration="N/A"
+streams.stream.1.tags.DURATION="00:24:00.032000000"
+streams.stream.2.index=2
+streams.stream.2.codec_name="mjpeg"
+streams.stream.2.codec_type="video"
+streams.stream.2.disposition.attached_pic=1
+chapters.chapter.0.start_time="0.000000"
+chapters.chapter.0.end_time="300.000000"
+chapters.chapter.0.tags.title="Opening"
+format.duration="1440.032000"
+format.tags.TITLE="Episode 1"
+"#;
+        let info = parse_flat(text);
+
+        assert_eq!(info.duration, Some(1440.032));
+        assert_eq!(info.streams.len(), 3);
+        assert_eq!(info.streams_of("video").len(), 1);
+        assert_eq!(info.cover_art()[0].index, 2);
+        assert_eq!(info.streams[1].language.as_deref(), Some("jpn"));
+        assert_eq!(info.streams[1].duration, Some(1440.032));
+        assert_eq!(info.streams[0].duration, None);
+        assert_eq!(info.chapters[0].end, 300.0);
+        assert_eq!(info.tags.get("title").map(String::as_str), Some("Episode 1"));
+    }
+}
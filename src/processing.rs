@@ -1,203 +1,290 @@
-use crate::gpu::{detect_gpu_from_devices};
-use crate::ledger::{append_to_ledger, load_ledger};
+use crate::config::{load_config, AppConfig};
+use crate::disposal::{self, SourceAction};
+use crate::hooks::{self, HookEvent, JOB_FAILED, JOB_QUEUED, JOB_SUCCEEDED};
+use crate::joblog::prune_logs;
+use crate::ledger::{
+    append_to_ledger, clear_failed, clear_interrupted, load_failed, load_interrupted, load_ledger, record_failure,
+    RetryPolicy,
+};
+use crate::preview;
+use crate::queue::{self, JobQueue};
+use crate::shutdown;
+use crate::stability::{self, Activity, Stability};
+use crate::transcode::{transcode_file, JobAction, JobOptions, JobOutcome, TranscodeError};
+use crate::webhook::Webhooks;
 use chrono::Local;
-use rayon::prelude::*;
 use std::collections::HashMap;
-use std::fs::{self, File};
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
-const TEMP_DIR: &str = "/tmp/video_convert_work";
-
-pub fn process_directory(watch_dir: &str) {
-    let (mkv_files, srt_files) = collect_files(watch_dir);
-    let ledger = load_ledger();
-    let gpu_type = detect_gpu_from_devices();
+/// Settings shared by every job found in one scan.
+#[derive(Debug)]
+pub struct JobSettings {
+    pub options: JobOptions,
+    pub webhooks: Webhooks,
+    pub retry_policy: RetryPolicy,
+}
 
-    fs::create_dir_all(TEMP_DIR).ok();
+/// A source waiting in the job queue.
+#[derive(Debug, Clone)]
+pub struct QueuedJob {
+    pub subtitle: Option<PathBuf>,
+    /// The job was cut short by the last shutdown.
+    pub resume: bool,
+    pub settings: Arc<JobSettings>,
+}
 
-    mkv_files.par_iter().for_each(|(base, input_file)| {
-        if ledger.contains(base) {
-            println!("✅ Skipped (already converted): {}", base);
-            return;
+impl JobSettings {
+    /// Detects the encoder and profile once; every scan queues jobs with the result.
+    pub fn from_config(cfg: &AppConfig) -> Self {
+        JobSettings {
+            options: JobOptions::detect(),
+            webhooks: Webhooks::new(
+                cfg.webhook_urls.clone(),
+                cfg.webhook_secret.clone(),
+                cfg.webhook_retries,
+                Path::new(&cfg.webhook_log),
+            ),
+            retry_policy: RetryPolicy {
+                max_attempts: cfg.max_attempts,
+                base_delay: Duration::from_secs(cfg.retry_base_secs),
+                max_delay: Duration::from_secs(cfg.retry_max_secs),
+            },
         }
+    }
+}
 
-        let temp_input = Path::new(TEMP_DIR).join(input_file.file_name().unwrap());
-        if let Err(e) = fs::copy(&input_file, &temp_input) {
-            println!("❌ Failed to copy to temp: {}", e);
-            return;
-        }
+pub type Queue = JobQueue<QueuedJob>;
 
-        let output_file = input_file.with_extension("mp4");
-        if output_file.exists() {
-            println!("🟡 Already exists: {:?}", output_file);
-            append_to_ledger(base);
-            return;
-        }
+/// Transcodes every finished source in `watch_dir` and waits for them. Returns
+/// how many sources are still arriving, so callers know a later rescan is needed.
+pub fn process_directory(watch_dir: &str) -> usize {
+    let cfg = load_config();
+    let settings = Arc::new(JobSettings::from_config(&cfg));
+    prune_logs(Path::new(&cfg.log_dir), cfg.log_retention_days);
+    let queue = Arc::new(Queue::new());
+    let mut settling = scan(watch_dir, &queue, &settings);
+    // A first sighting is never stable, so look once more after the interval
+    if settling > 0 {
+        thread::sleep(Duration::from_secs(cfg.stable_secs));
+        settling = scan(watch_dir, &queue, &settings);
+    }
+    queue.close();
+    for worker in start_workers(&queue, cfg.threads) {
+        let _ = worker.join();
+    }
+    settling
+}
+
+/// Starts `count` workers running queued jobs until the queue is closed.
+pub fn start_workers(queue: &Arc<Queue>, count: usize) -> Vec<JoinHandle<()>> {
+    queue::start_workers(queue, count, process_job)
+}
 
-        let srt_file = srt_files.get(base);
-        let log_file = output_file.with_extension("log");
+/// Queues every finished source in `watch_dir` that isn't converted, waiting for
+/// a retry, or already queued. Returns how many sources are still arriving.
+pub fn scan(watch_dir: &str, queue: &Queue, settings: &Arc<JobSettings>) -> usize {
+    let ledger = load_ledger();
+    let failed = load_failed();
+    let interrupted = load_interrupted();
+    let cfg = load_config();
+    let options = &settings.options;
+    let (mkv_files, srt_files) = collect_files(watch_dir, &options.profile.source_action);
 
+    // A new profile waits until samples of it have been looked at and approved
+    if cfg.require_preview && !preview::is_approved(&options.profile) {
         println!(
-            "\n[{}] 🎬 Converting: {:?}",
-            Local::now().format("%Y-%m-%d %H:%M:%S"),
-            input_file
+            "⏸️ Profile {} has no approved preview, not queueing (see `preview` and `approve-preview`)",
+            options.profile.name
         );
-        let start_time = std::time::Instant::now();
-
-        let mut command = Command::new("ffmpeg");
-        command.arg("-y");
-
-        // Step 1: Add video input
-        match gpu_type {
-            "nvenc" => {
-                command
-                    .arg("-hwaccel")
-                    .arg("cuda")
-                    .arg("-i")
-                    .arg(&temp_input);
-            }
-            "vaapi" => {
-                command
-                    .arg("-hwaccel")
-                    .arg("vaapi")
-                    .arg("-vaapi_device")
-                    .arg("/dev/dri/renderD128")
-                    .arg("-i")
-                    .arg(&temp_input);
-            }
-            _ => {
-                println!("⚠️ GPU not available or unsupported, falling back to CPU encoding.");
-                command.arg("-i").arg(&temp_input);
-            }
-        }
+        return 0;
+    }
 
-        // Step 2: Add subtitle input if available
-        if let Some(srt_path) = srt_file {
-            let temp_srt = Path::new(TEMP_DIR).join(srt_path.file_name().unwrap());
-            match fs::copy(srt_path, &temp_srt) {
-                Ok(_) => println!("💬 Subtitle copied to temp: {:?}", temp_srt),
-                Err(e) => {
-                    println!("❌ Failed to copy subtitle to temp: {}", e);
-                    return;
+    // Only queue sources that have finished arriving, in both inotify and polling mode.
+    // Open files and locks are only looked up once some source needs checking.
+    let mut activity: Option<Activity> = None;
+    let interval = Duration::from_secs(cfg.stable_secs);
+    let mut settling = 0;
+    for (base, input_file) in &mkv_files {
+        if ledger.contains(base) {
+            println!("✅ Skipped (already converted): {}", base);
+            continue;
+        }
+        if queue.contains(input_file) {
+            continue;
+        }
+        match failed.get(base) {
+            Some(record) if record.is_permanent() => {
+                println!(
+                    "⛔ Skipped (failed {} times, last: {}): {}",
+                    record.attempts, record.last_error, base
+                );
+                continue;
+            }
+            Some(record) if !record.is_due(Local::now()) => {
+                if let Some(at) = record.next_retry {
+                    println!("🔁 Retrying {} after {}", base, at.format("%Y-%m-%d %H:%M:%S"));
                 }
+                continue;
             }
+            _ => {}
+        }
+        if options.hooks.is_vetoed(input_file) {
+            println!("🚫 Skipped (vetoed, unchanged since): {}", base);
+            continue;
+        }
+        let sources = std::iter::once(input_file).chain(srt_files.get(base));
+        let stable = sources
+            .map(|path| stability::check(path, interval, activity.get_or_insert_with(Activity::snapshot)))
+            .all(|s| s == Stability::Stable);
+        if !stable {
+            println!("⏳ Still arriving: {}", base);
+            settling += 1;
+            continue;
+        }
 
-            command.arg("-f").arg("srt").arg("-i").arg(&temp_srt);
+        if shutdown::requested() {
+            break;
+        }
+        let queued = HookEvent {
+            event: JOB_QUEUED,
+            input: input_file.clone(),
+            profile: options.profile.name.clone(),
+            input_size: fs::metadata(input_file).ok().map(|m| m.len()),
+            ..HookEvent::default()
+        };
+        if let Err(reason) = options.hooks.run(&queued) {
+            println!("🚫 Not queued {}: {}", base, reason);
+            hooks::remember_veto(input_file);
+            continue;
         }
 
-        // Step 3: Mapping and codec configuration
-        if srt_file.is_some() {
-            command
-                .arg("-map")
-                .arg("0:v:0")
-                .arg("-map")
-                .arg("0:a?")
-                .arg("-map")
-                .arg("1:s:0");
+        let resume = interrupted.contains(base);
+        let job = QueuedJob {
+            subtitle: srt_files.get(base).cloned(),
+            resume,
+            settings: Arc::clone(settings),
+        };
+        // Jobs cut short by the last shutdown go first
+        if resume {
+            queue.push_front(input_file.clone(), job);
         } else {
-            println!("🕳️ No subtitle found for: {:?}", input_file);
-            command.arg("-map").arg("0:v:0").arg("-map").arg("0:a?");
+            queue.push(input_file.clone(), job);
         }
+    }
 
-        // Step 4: Video codec
-        match gpu_type {
-            "nvenc" => {
-                command.arg("-c:v").arg("h264_nvenc");
-            }
-            "vaapi" => {
-                command
-                    .arg("-vf")
-                    .arg("format=nv12,hwupload")
-                    .arg("-c:v")
-                    .arg("h264_vaapi");
-            }
-            _ => {
-                command.arg("-c:v").arg("libx264");
-            }
-        }
+    settling
+}
 
-        // Subtitle codec if present
-        if srt_file.is_some() {
-            command
-                .arg("-c:s")
-                .arg("mov_text")
-                .arg("-metadata:s:s:0")
-                .arg("language=eng");
+/// Runs one queued job and records its outcome.
+pub fn process_job(input_file: &Path, job: &QueuedJob) {
+    if shutdown::requested() {
+        return;
+    }
+    let base = &*input_file.file_stem().unwrap_or_default().to_string_lossy();
+    if job.resume {
+        println!("▶️ Resuming interrupted job: {}", base);
+    }
+    stability::forget(input_file);
+    if let Some(srt) = &job.subtitle {
+        stability::forget(srt);
+    }
+
+    let options = JobOptions {
+        subtitle: job.subtitle.clone(),
+        ..job.settings.options.clone()
+    };
+    match transcode_file(input_file, &options) {
+        Ok(outcome) => record_success(job, &outcome),
+        Err(TranscodeError::Deferred(reason)) => {
+            println!("⏸️ Deferred {}: {}", base, reason);
+        }
+        Err(TranscodeError::Vetoed(reason)) => {
+            println!("🚫 Not started {}: {}", base, reason);
+            hooks::remember_veto(input_file);
+        }
+        Err(TranscodeError::Interrupted) => {
+            println!("⏹️ Interrupted: {}", base);
+            return;
         }
+        Err(e) => record_error(input_file, job, &e.to_string()),
+    }
+    if job.resume {
+        clear_interrupted(base);
+    }
+}
 
-        // Audio and container options
-        command
-            .arg("-c:a")
-            .arg("aac")
-            .arg("-b:a")
-            .arg("128k")
-            .arg("-profile:v")
-            .arg("main")
-            .arg("-level:v")
-            .arg("4.0")
-            .arg("-movflags")
-            .arg("+faststart")
-            .arg(&output_file);
-
-        // Print for debugging
+/// Marks a finished job as converted and announces it (unless it was skipped).
+pub fn record_success(job: &QueuedJob, outcome: &JobOutcome) {
+    let base = &*outcome.input.file_stem().unwrap_or_default().to_string_lossy();
+    if outcome.action != JobAction::Skipped {
         println!(
-            "🛠️ Running ffmpeg command: ffmpeg {}",
-            command
-                .get_args()
-                .map(|a| a.to_string_lossy())
-                .collect::<Vec<_>>()
-                .join(" ")
+            "📊 {}: {:?}, {} → {} bytes",
+            base, outcome.action, outcome.input_size, outcome.output_size
         );
+        job.settings.webhooks.send(HookEvent {
+            event: JOB_SUCCEEDED,
+            input: outcome.input.clone(),
+            output: Some(outcome.output.clone()),
+            profile: job.settings.options.profile.name.clone(),
+            duration: outcome.media_duration,
+            input_size: Some(outcome.input_size),
+            output_size: Some(outcome.output_size),
+            error: None,
+        });
+    }
+    append_to_ledger(base);
+    clear_failed(base);
+}
 
-        match command.spawn() {
-            Ok(mut child) => {
-                println!("🚀 PID: {}", child.id());
-                match child.wait() {
-                    Ok(status) => {
-                        if !status.success() {
-                            println!("💥 ffmpeg exited with error: {:?}", status);
-                            println!("📄 Check log file: {}", log_file.display());
-                            return;
-                        }
-                    }
-                    Err(e) => {
-                        println!("💥 Failed to wait on ffmpeg: {}", e);
-                        return;
-                    }
-                }
-
-                let duration = start_time.elapsed();
-                println!("🏁 Done {} in {:.2?}", base, duration);
-
-                match fs::copy(&output_file, &input_file.with_extension("converted.mp4")) {
-                    Ok(_) => {
-                        println!(
-                            "📝 Copied from {} to {}",
-                            output_file.display(),
-                            input_file.with_extension("converted.mp4").display()
-                        );
-                    }
-                    Err(e) => {
-                        println!("⚠️ Failed to copy converted.mp4: {}", e);
-                    }
-                }
-
-                append_to_ledger(base);
-            }
-            Err(e) => {
-                println!("💥 Failed to spawn ffmpeg: {}", e);
-            }
-        }
+/// Records a failed attempt, schedules the retry and announces the failure.
+pub fn record_error(input_file: &Path, job: &QueuedJob, error: &str) {
+    let base = &*input_file.file_stem().unwrap_or_default().to_string_lossy();
+    let JobSettings { options, webhooks, retry_policy } = &*job.settings;
+    println!("💥 {} failed: {}", base, error);
+    let record = record_failure(base, error, retry_policy);
+    match record.next_retry {
+        Some(at) => println!(
+            "🔁 Attempt {} of {} for {}, next at {}",
+            record.attempts,
+            retry_policy.max_attempts,
+            base,
+            at.format("%Y-%m-%d %H:%M:%S")
+        ),
+        None => println!("⛔ Giving up on {} after {} attempts", base, record.attempts),
+    }
+    webhooks.send(HookEvent {
+        event: JOB_FAILED,
+        input: input_file.to_path_buf(),
+        output: Some(input_file.with_extension("mp4")),
+        profile: options.profile.name.clone(),
+        input_size: fs::metadata(input_file).ok().map(|m| m.len()),
+        error: Some(error.to_string()),
+        ..HookEvent::default()
     });
 }
 
-fn collect_files(watch_dir: &str) -> (HashMap<String, PathBuf>, HashMap<String, PathBuf>) {
+fn collect_files(
+    watch_dir: &str,
+    source_action: &SourceAction,
+) -> (HashMap<String, PathBuf>, HashMap<String, PathBuf>) {
     let mut mkv_files = HashMap::new();
     let mut srt_files = HashMap::new();
 
+    // Hidden folders and the archive or trash folder hold sources already dealt
+    // with; trashed ones carry a stamped stem the ledger has never seen
+    let skipped = |entry: &walkdir::DirEntry| {
+        entry.depth() > 0
+            && entry.file_type().is_dir()
+            && (entry.file_name().to_string_lossy().starts_with('.')
+                || disposal::is_disposal_dir(source_action, entry.path()))
+    };
     for entry in walkdir::WalkDir::new(watch_dir)
         .into_iter()
+        .filter_entry(|e| !skipped(e))
         .filter_map(Result::ok)
         .filter(|e| e.path().is_file())
     {
@@ -234,10 +321,49 @@ mod tests {
         File::create(&mkv_path).unwrap();
         File::create(&srt_path).unwrap();
 
-        let (mkv_map, srt_map) = collect_files(tmp_dir);
+        let (mkv_map, srt_map) = collect_files(tmp_dir, &SourceAction::Keep);
         assert!(mkv_map.contains_key("video1"));
         assert!(srt_map.contains_key("video1"));
 
         let _ = fs::remove_dir_all(tmp_dir);
     }
+
+    #[test]
+    fn test_trashed_source_is_not_requeued() {
+        let watch_dir = std::env::temp_dir().join("test_processing_trashed");
+        let show = watch_dir.join("show");
+        let watch = watch_dir.to_str().unwrap();
+
+        let actions = [
+            SourceAction::Trash { dir: ".trash".into(), retention_days: 30 },
+            SourceAction::Trash { dir: "trash".into(), retention_days: 30 },
+            SourceAction::Archive(watch_dir.join("archive")),
+        ];
+        for action in &actions {
+            let _ = fs::remove_dir_all(&watch_dir);
+            fs::create_dir_all(&show).unwrap();
+            let source = show.join("e01.mkv");
+            File::create(&source).unwrap();
+            File::create(show.join("e01.mp4")).unwrap();
+            let moved = disposal::apply(action, &source, &show.join("e01.mp4")).unwrap().unwrap();
+            assert!(moved.exists());
+
+            let (mkv_map, _) = collect_files(watch, action);
+            assert!(mkv_map.is_empty(), "{:?} re-queued {:?}", action, mkv_map);
+        }
+
+        // A folder of the same name that no source could have been moved into is scanned
+        let _ = fs::remove_dir_all(&watch_dir);
+        fs::create_dir_all(&show).unwrap();
+        File::create(show.join("e01.mkv")).unwrap();
+        File::create(show.join("e01.mp4")).unwrap();
+        disposal::apply(&actions[1], &show.join("e01.mkv"), &show.join("e01.mp4")).unwrap();
+        let own = watch_dir.join("projects").join("trash");
+        fs::create_dir_all(&own).unwrap();
+        File::create(own.join("keeper.mkv")).unwrap();
+        let (mkv_map, _) = collect_files(watch, &actions[1]);
+        assert_eq!(mkv_map.keys().collect::<Vec<_>>(), vec!["keeper"]);
+
+        let _ = fs::remove_dir_all(&watch_dir);
+    }
 }
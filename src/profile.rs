@@ -0,0 +1,171 @@
+use crate::disposal::{parse_source_action, SourceAction};
+use crate::metadata::{parse_policy, MetadataPolicy};
+use crate::sidecar::parse_kv;
+use crate::thumbnails::{parse_grid, parse_target, ThumbnailTarget};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+const DEFAULT_PROFILE_DIR: &str = "/etc/video_transcoder/profiles";
+
+/// An encoding profile, loaded from `<PROFILE_DIR>/<name>.profile`:
+///
+/// ```text
+/// chapters = keep
+/// global_tags = rewrite title={stem}
+/// stream_titles = strip
+/// attachments = keep
+/// encoder_tag = strip
+/// cpu_overflow = true
+/// source_action = trash /mnt/media/.trash
+/// trash_retention_days = 14
+/// thumbnails = .thumbs
+/// contact_sheet = 4x4
+/// ```
+///
+/// Keys that are left out keep their defaults. Values containing ` #` must be
+/// quoted, e.g. `global_tags = "rewrite comment=Episode #1"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub metadata: MetadataPolicy,
+    /// Encode on the CPU when every GPU slot is taken, instead of waiting.
+    pub cpu_overflow: bool,
+    /// What to do with the source after a verified success.
+    pub source_action: SourceAction,
+    /// Where to write a poster frame and contact sheet for each output.
+    pub thumbnails: ThumbnailTarget,
+    /// Contact sheet grid, `(columns, rows)`.
+    pub contact_sheet: (u32, u32),
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            name: "default".into(),
+            metadata: MetadataPolicy::default(),
+            cpu_overflow: false,
+            source_action: SourceAction::Keep,
+            thumbnails: ThumbnailTarget::Off,
+            contact_sheet: (4, 4),
+        }
+    }
+}
+
+impl Profile {
+    /// Identifies these settings, so a changed profile can be told from the one
+    /// something was approved or handed out with.
+    pub fn fingerprint(&self) -> String {
+        let hash = hmac_sha256::Hash::hash(format!("{:?}", self).as_bytes());
+        hash[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Loads the profile named by `PROFILE` (default "default"), falling back to
+/// built-in defaults when it can't be read.
+pub fn active_profile() -> Profile {
+    let name = std::env::var("PROFILE").unwrap_or_else(|_| "default".into());
+    match load_profile(&name) {
+        Ok(profile) => profile,
+        Err(e) => {
+            println!("⚠️ {} — using built-in defaults", e);
+            Profile { name, ..Profile::default() }
+        }
+    }
+}
+
+pub fn load_profile(name: &str) -> Result<Profile, String> {
+    let dir = std::env::var("PROFILE_DIR").unwrap_or_else(|_| DEFAULT_PROFILE_DIR.into());
+    let path = Path::new(&dir).join(format!("{}.profile", name));
+    match fs::read_to_string(&path) {
+        Ok(text) => parse_profile(name, &text),
+        // The default profile doesn't need a file
+        Err(e) if e.kind() == ErrorKind::NotFound && name == "default" => Ok(Profile::default()),
+        Err(e) => Err(format!("Cannot read profile {}: {}", path.display(), e)),
+    }
+}
+
+pub fn parse_profile(name: &str, text: &str) -> Result<Profile, String> {
+    let mut profile = Profile { name: name.to_string(), ..Profile::default() };
+    let mut trash_retention = None;
+
+    for (line, key, value) in parse_kv(text) {
+        let metadata = &mut profile.metadata;
+        let result = match key {
+            "chapters" => parse_policy(value).map(|p| metadata.chapters = p),
+            "global_tags" => parse_policy(value).map(|p| metadata.global_tags = p),
+            "stream_titles" => parse_policy(value).map(|p| metadata.stream_titles = p),
+            "attachments" => parse_policy(value).map(|p| metadata.attachments = p),
+            "encoder_tag" => parse_policy(value).map(|p| metadata.encoder_tag = p),
+            "cpu_overflow" => parse_bool(value).map(|b| profile.cpu_overflow = b),
+            "source_action" => parse_source_action(value).map(|a| profile.source_action = a),
+            "thumbnails" => parse_target(value).map(|t| profile.thumbnails = t),
+            "contact_sheet" => parse_grid(value).map(|g| profile.contact_sheet = g),
+            "trash_retention_days" => value
+                .parse()
+                .map(|days| trash_retention = Some((line, days)))
+                .map_err(|_| format!("invalid number of days {:?}", value)),
+            _ => Err(format!("unknown key {:?}", key)),
+        };
+        result.map_err(|e| format!("Profile {} line {}: {}", name, line, e))?;
+    }
+
+    if let Some((line, days)) = trash_retention {
+        match &mut profile.source_action {
+            SourceAction::Trash { retention_days, .. } => *retention_days = days,
+            _ => return Err(format!("Profile {} line {}: trash_retention_days needs source_action = trash", name, line)),
+        }
+    }
+
+    Ok(profile)
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        _ => Err(format!("invalid boolean {:?}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Policy;
+
+    #[test]
+    fn test_parse_profile() {
+        let text = "chapters = strip\nglobal_tags = rewrite title={stem}\ncpu_overflow = yes\ncontact_sheet = 3x2\n";
+        let profile = parse_profile("anime", text).unwrap();
+
+        assert_eq!(profile.name, "anime");
+        assert_eq!(profile.metadata.chapters, Policy::Strip);
+        assert_eq!(profile.metadata.global_tags, Policy::Rewrite("title={stem}".into()));
+        assert_eq!(profile.metadata.attachments, Policy::Strip);
+        assert!(profile.cpu_overflow);
+        assert_eq!(profile.contact_sheet, (3, 2));
+        assert_eq!(profile.thumbnails, ThumbnailTarget::Off);
+    }
+
+    #[test]
+    fn test_parse_profile_quoted_value() {
+        let profile = parse_profile("tv", "global_tags = \"rewrite comment=Episode #1\" # season 1\n").unwrap();
+        assert_eq!(profile.metadata.global_tags, Policy::Rewrite("comment=Episode #1".into()));
+    }
+
+    #[test]
+    fn test_parse_profile_rejects_unknown_keys() {
+        let err = parse_profile("bad", "chapters = keep\ncolour = blue\n").unwrap_err();
+        assert!(err.contains("line 2"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_profile_source_action() {
+        let text = "trash_retention_days = 7\nsource_action = trash .trash\n";
+        let profile = parse_profile("tv", text).unwrap();
+        assert_eq!(profile.source_action, SourceAction::Trash { dir: ".trash".into(), retention_days: 7 });
+
+        let err = parse_profile("tv", "source_action = delete\ntrash_retention_days = 7\n").unwrap_err();
+        assert!(err.contains("line 2"), "{}", err);
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often a running job prints a progress line.
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Progress of a running ffmpeg job, as reported by `-progress pipe:1`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Progress {
+    pub frame: u64,
+    pub fps: f64,
+    /// Position in the output, in seconds.
+    pub out_time: f64,
+    /// Encode speed relative to real time (2.0 means twice as fast as playback).
+    pub speed: Option<f64>,
+    /// Percentage of the probed source duration, when known.
+    pub percent: Option<f64>,
+    pub eta: Option<Duration>,
+    pub finished: bool,
+}
+
+impl Progress {
+    pub fn summary(&self) -> String {
+        let percent = self.percent.map_or("?".to_string(), |p| format!("{:.1}%", p));
+        let speed = self.speed.map_or("?".to_string(), |s| format!("{:.2}x", s));
+        let eta = self.eta.map_or("?".to_string(), |e| format_clock(e.as_secs_f64()));
+        format!(
+            "{} frame={} fps={:.1} speed={} time={} eta={}",
+            percent,
+            self.frame,
+            self.fps,
+            speed,
+            format_clock(self.out_time),
+            eta
+        )
+    }
+}
+
+/// Turns the `key=value` stream from `-progress` into `Progress` snapshots.
+pub struct ProgressParser {
+    current: Progress,
+    duration: Option<f64>,
+}
+
+impl ProgressParser {
+    /// `duration` is the probed source duration in seconds, used for percentage and ETA.
+    pub fn new(duration: Option<f64>) -> Self {
+        ProgressParser {
+            current: Progress::default(),
+            duration: duration.filter(|d| *d > 0.0),
+        }
+    }
+
+    /// Feeds one line. Returns a snapshot at the end of each block (`progress=continue|end`).
+    pub fn feed(&mut self, line: &str) -> Option<Progress> {
+        let (key, value) = line.trim().split_once('=')?;
+        let value = value.trim();
+        match key {
+            "frame" => self.current.frame = value.parse().unwrap_or(self.current.frame),
+            "fps" => self.current.fps = value.parse().unwrap_or(self.current.fps),
+            // Despite the name, out_time_ms is in microseconds, same as out_time_us
+            "out_time_us" | "out_time_ms" => {
+                if let Ok(us) = value.parse::<i64>() {
+                    self.current.out_time = us.max(0) as f64 / 1_000_000.0;
+                }
+            }
+            "speed" => self.current.speed = value.trim_end_matches('x').parse().ok(),
+            "progress" => {
+                self.current.finished = value == "end";
+                self.update_estimates();
+                return Some(self.current.clone());
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn update_estimates(&mut self) {
+        let Some(duration) = self.duration else {
+            return;
+        };
+        let p = &mut self.current;
+        p.percent = Some(if p.finished { 100.0 } else { (p.out_time / duration * 100.0).min(100.0) });
+        p.eta = match (p.finished, p.speed) {
+            (true, _) => Some(Duration::ZERO),
+            (false, Some(speed)) if speed > 0.0 => {
+                Some(Duration::from_secs_f64((duration - p.out_time).max(0.0) / speed))
+            }
+            _ => None,
+        };
+    }
+}
+
+static ACTIVE: Mutex<BTreeMap<String, Progress>> = Mutex::new(BTreeMap::new());
+
+/// Latest progress of every running job, keyed by job name.
+pub fn snapshot() -> Vec<(String, Progress)> {
+    let active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    active.iter().map(|(job, p)| (job.clone(), p.clone())).collect()
+}
+
+/// Latest progress of one running job.
+pub fn current(job: &str) -> Option<Progress> {
+    let active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    active.get(job).cloned()
+}
+
+fn publish(job: &str, progress: &Progress) {
+    let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    active.insert(job.to_string(), progress.clone());
+}
+
+fn retire(job: &str) {
+    let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    active.remove(job);
+}
+
+/// Reads ffmpeg's progress output until it closes, publishing each snapshot and
+/// printing one every `LOG_INTERVAL`. Returns the last snapshot seen.
+pub fn track(job: &str, output: impl Read, duration: Option<f64>) -> Option<Progress> {
+    let mut parser = ProgressParser::new(duration);
+    let mut last = None;
+    let mut last_logged = Instant::now();
+
+    // Read raw lines, so a stray non-UTF-8 byte can't stop the pipe being drained
+    for line in BufReader::new(output).split(b'\n').map_while(Result::ok) {
+        if let Some(progress) = parser.feed(&String::from_utf8_lossy(&line)) {
+            publish(job, &progress);
+            if last_logged.elapsed() >= LOG_INTERVAL {
+                println!("⏳ {}: {}", job, progress.summary());
+                last_logged = Instant::now();
+            }
+            last = Some(progress);
+        }
+    }
+
+    retire(job);
+    last
+}
+
+fn format_clock(seconds: f64) -> String {
+    let s = seconds.max(0.0) as u64;
+    format!("{:02}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: &str = "frame=250\nfps=50.00\nstream_0_0_q=23.0\nbitrate=1200.0kbits/s\n\
+                         out_time_us=10000000\nout_time_ms=10000000\nout_time=00:00:10.000000\n\
+                         dup_frames=0\ndrop_frames=0\nspeed=2.5x\nprogress=continue\n";
+
+    #[test]
+    fn test_parser_computes_percent_and_eta() {
+        let mut parser = ProgressParser::new(Some(60.0));
+        let snapshots: Vec<Progress> = BLOCK.lines().filter_map(|l| parser.feed(l)).collect();
+
+        assert_eq!(snapshots.len(), 1);
+        let p = &snapshots[0];
+        assert_eq!((p.frame, p.fps, p.out_time, p.speed), (250, 50.0, 10.0, Some(2.5)));
+        assert!((p.percent.unwrap() - 16.666).abs() < 0.01);
+        assert_eq!(p.eta, Some(Duration::from_secs(20)));
+        assert!(!p.finished);
+    }
+
+    #[test]
+    fn test_track_publishes_until_end() {
+        let stream = [BLOCK.as_bytes(), b"encoder=\xff\n", b"frame=300\nspeed=N/A\nprogress=end\n"].concat();
+        let last = track("test_track_job", &stream[..], None).unwrap();
+
+        assert!(last.finished);
+        assert_eq!(last.speed, None);
+        assert_eq!(last.percent, None);
+        assert!(snapshot().iter().all(|(job, _)| job != "test_track_job"));
+    }
+}
@@ -0,0 +1,72 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Moves a verified output from the staging area to `dest`, so that `dest` only
+/// ever appears once it holds the complete file.
+///
+/// A plain rename is used when possible. Across filesystems (e.g. local temp dir
+/// to an SMB share) the file is copied to a hidden name beside `dest`, flushed,
+/// and then renamed into place.
+pub fn publish(staged: &Path, dest: &Path) -> io::Result<()> {
+    if fs::rename(staged, dest).is_ok() {
+        return Ok(());
+    }
+    publish_by_copy(staged, dest)
+}
+
+fn publish_by_copy(staged: &Path, dest: &Path) -> io::Result<()> {
+    let partial = partial_path(dest);
+    let result = fs::copy(staged, &partial)
+        .and_then(|_| File::open(&partial)?.sync_all())
+        .and_then(|_| fs::rename(&partial, dest));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    fs::remove_file(staged)
+}
+
+/// `dir/.name.tmp` — hidden and without a media extension, so scans never pick it up.
+pub fn partial_path(dest: &Path) -> PathBuf {
+    let name = dest.file_name().unwrap_or_default().to_string_lossy();
+    dest.with_file_name(format!(".{}.tmp", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_renames_into_place() {
+        let dir = std::env::temp_dir().join("test_publish_renames");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("work")).unwrap();
+        let staged = dir.join("work").join("movie.mp4");
+        fs::write(&staged, b"complete").unwrap();
+
+        publish(&staged, &dir.join("movie.mp4")).unwrap();
+        assert!(!staged.exists());
+        assert_eq!(fs::read(dir.join("movie.mp4")).unwrap(), b"complete");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_publish_by_copy_leaves_no_partial() {
+        let dir = std::env::temp_dir().join("test_publish_by_copy");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let staged = dir.join("staged.mp4");
+        let dest = dir.join("movie.mp4");
+        fs::write(&staged, b"complete").unwrap();
+
+        publish_by_copy(&staged, &dest).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b"complete");
+        assert!(!staged.exists());
+        assert!(!partial_path(&dest).exists());
+        assert_eq!(partial_path(&dest), dir.join(".movie.mp4.tmp"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
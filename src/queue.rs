@@ -0,0 +1,207 @@
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// The one queue all watchers feed and all workers take jobs from.
+///
+/// Jobs are keyed by source path: a path that is already waiting or being
+/// processed can't be queued again, so however many events or scans report a
+/// file, it is processed once at a time.
+#[derive(Debug)]
+pub struct JobQueue<T> {
+    state: Mutex<State<T>>,
+    ready: Condvar,
+}
+
+#[derive(Debug)]
+struct State<T> {
+    pending: VecDeque<(PathBuf, T)>,
+    in_flight: HashSet<PathBuf>,
+    closed: bool,
+}
+
+/// A job taken off the queue. Its path counts as in flight until this is dropped.
+#[derive(Debug)]
+pub struct Claim<'a, T> {
+    queue: &'a JobQueue<T>,
+    pub path: PathBuf,
+    pub job: T,
+}
+
+impl<T> Default for JobQueue<T> {
+    fn default() -> Self {
+        JobQueue {
+            state: Mutex::new(State { pending: VecDeque::new(), in_flight: HashSet::new(), closed: false }),
+            ready: Condvar::new(),
+        }
+    }
+}
+
+impl<T> JobQueue<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// True when `path` is waiting or being processed.
+    pub fn contains(&self, path: &Path) -> bool {
+        let state = self.lock();
+        state.in_flight.contains(path) || state.pending.iter().any(|(p, _)| p == path)
+    }
+
+    /// Queues `job` at the back. Returns false (dropping `job`) if `path` is already
+    /// waiting or being processed, or the queue is closed.
+    pub fn push(&self, path: PathBuf, job: T) -> bool {
+        self.insert(path, job, false)
+    }
+
+    /// Like `push`, but ahead of everything already waiting.
+    pub fn push_front(&self, path: PathBuf, job: T) -> bool {
+        self.insert(path, job, true)
+    }
+
+    fn insert(&self, path: PathBuf, job: T, front: bool) -> bool {
+        let mut state = self.lock();
+        if state.closed || state.in_flight.contains(&path) || state.pending.iter().any(|(p, _)| *p == path) {
+            return false;
+        }
+        if front {
+            state.pending.push_front((path, job));
+        } else {
+            state.pending.push_back((path, job));
+        }
+        self.ready.notify_one();
+        true
+    }
+
+    /// Waits for the next job. Returns `None` once the queue is closed and empty.
+    pub fn pop(&self) -> Option<Claim<'_, T>> {
+        let mut state = self.lock();
+        loop {
+            if let Some((path, job)) = state.pending.pop_front() {
+                state.in_flight.insert(path.clone());
+                return Some(Claim { queue: self, path, job });
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.ready.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Takes the next waiting job without waiting, for handing to another process.
+    /// Its path stays in flight until `release`d.
+    pub fn take(&self) -> Option<(PathBuf, T)> {
+        self.take_where(|_| true)
+    }
+
+    /// Like `take`, but skips jobs whose path `wanted` turns down; they keep their place.
+    pub fn take_where(&self, wanted: impl Fn(&Path) -> bool) -> Option<(PathBuf, T)> {
+        let mut state = self.lock();
+        let index = state.pending.iter().position(|(path, _)| wanted(path))?;
+        let (path, job) = state.pending.remove(index)?;
+        state.in_flight.insert(path.clone());
+        Some((path, job))
+    }
+
+    /// Ends a job obtained with `take`, so its path can be queued again.
+    pub fn release(&self, path: &Path) {
+        self.lock().in_flight.remove(path);
+    }
+
+    /// Stops accepting jobs; workers exit once the waiting ones are done.
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.ready.notify_all();
+    }
+
+    /// Number of jobs waiting (not counting those in flight).
+    pub fn len(&self) -> usize {
+        self.lock().pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for Claim<'_, T> {
+    fn drop(&mut self) {
+        self.queue.release(&self.path);
+    }
+}
+
+/// Starts `count` workers that run `handler` on queued jobs until the queue is closed.
+pub fn start_workers<T, F>(queue: &Arc<JobQueue<T>>, count: usize, handler: F) -> Vec<JoinHandle<()>>
+where
+    T: Send + 'static,
+    F: Fn(&Path, &T) + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    (0..count.max(1))
+        .map(|_| {
+            let queue = Arc::clone(queue);
+            let handler = Arc::clone(&handler);
+            thread::spawn(move || {
+                while let Some(claim) = queue.pop() {
+                    handler(&claim.path, &claim.job);
+                }
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_push_dedupes_waiting_and_in_flight_paths() {
+        let queue = JobQueue::new();
+        assert!(queue.push(PathBuf::from("/watch/a.mkv"), 1));
+        assert!(!queue.push(PathBuf::from("/watch/a.mkv"), 2));
+        assert!(queue.push_front(PathBuf::from("/watch/b.mkv"), 3));
+
+        let claim = queue.pop().unwrap();
+        assert_eq!((claim.path.as_path(), claim.job), (Path::new("/watch/b.mkv"), 3));
+        assert!(!queue.push(PathBuf::from("/watch/b.mkv"), 4));
+        assert!(queue.contains(Path::new("/watch/b.mkv")));
+
+        drop(claim);
+        assert!(!queue.contains(Path::new("/watch/b.mkv")));
+        assert!(queue.push(PathBuf::from("/watch/b.mkv"), 5));
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn test_workers_drain_queue_without_overlap() {
+        let queue = Arc::new(JobQueue::new());
+        for i in 0..20 {
+            queue.push(PathBuf::from(format!("/watch/{}.mkv", i % 5)), i);
+        }
+        assert_eq!(queue.len(), 5);
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicUsize::new(0));
+        let (r, d) = (Arc::clone(&running), Arc::clone(&done));
+        let workers = start_workers(&queue, 3, move |_, _: &i32| {
+            assert!(r.fetch_add(1, Ordering::SeqCst) < 3);
+            std::thread::sleep(Duration::from_millis(20));
+            r.fetch_sub(1, Ordering::SeqCst);
+            d.fetch_add(1, Ordering::SeqCst);
+        });
+
+        queue.close();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(done.load(Ordering::SeqCst), 5);
+        assert!(!queue.push(PathBuf::from("/watch/late.mkv"), 99));
+    }
+}
@@ -0,0 +1,236 @@
+use std::fs;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const CPU_MAX_PERIOD_US: u64 = 100_000;
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+/// I/O scheduling class, as with `ionice -c`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoClass {
+    Realtime(u8),
+    BestEffort(u8),
+    Idle,
+}
+
+impl IoClass {
+    fn ioprio(self) -> libc::c_int {
+        let (class, level) = match self {
+            IoClass::Realtime(level) => (1, level),
+            IoClass::BestEffort(level) => (2, level),
+            IoClass::Idle => (3, 0),
+        };
+        (class << IOPRIO_CLASS_SHIFT) | libc::c_int::from(level)
+    }
+}
+
+/// Priority and CPU limits for spawned ffmpeg processes, so encodes leave room
+/// for whatever else runs on the box.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcessLimits {
+    /// Niceness, -20 (highest priority) to 19.
+    pub nice: Option<i32>,
+    pub io_class: Option<IoClass>,
+    /// CPUs ffmpeg may run on.
+    pub cpus: Option<Vec<usize>>,
+    /// ffmpeg `-threads` per job.
+    pub threads: Option<usize>,
+}
+
+impl ProcessLimits {
+    /// Makes `command` apply these limits to the child right before it execs.
+    pub fn apply(&self, command: &mut Command) {
+        if self.nice.is_none() && self.io_class.is_none() && self.cpus.is_none() {
+            return;
+        }
+        let nice = self.nice;
+        let ioprio = self.io_class.map(IoClass::ioprio);
+        // Built here: only async-signal-safe calls are allowed between fork and exec
+        let cpu_set = self.cpus.as_ref().map(|cpus| {
+            // SAFETY: cpu_set_t is plain data and CPU_ZERO/CPU_SET only write into it
+            let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+            for &cpu in cpus {
+                unsafe { libc::CPU_SET(cpu, &mut set) };
+            }
+            set
+        });
+
+        // SAFETY: the closure only makes raw syscalls on the child itself
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(nice) = nice {
+                    if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                if let Some(ioprio) = ioprio {
+                    if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                if let Some(set) = &cpu_set {
+                    if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), set) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+/// Limits from the FFMPEG_* settings; invalid values are reported and ignored.
+pub fn configured_limits(
+    nice: Option<i32>,
+    ionice: Option<&str>,
+    cpus: Option<&str>,
+    threads: Option<usize>,
+) -> ProcessLimits {
+    let warn = |setting: &str, e: String| println!("⚠️ Ignoring {}: {}", setting, e);
+    ProcessLimits {
+        nice,
+        io_class: ionice.and_then(|v| parse_io_class(v).map_err(|e| warn("FFMPEG_IONICE", e)).ok()),
+        cpus: cpus.and_then(|v| parse_cpu_list(v).map_err(|e| warn("FFMPEG_CPUS", e)).ok()),
+        threads,
+    }
+}
+
+/// Parses `idle`, `best-effort[:0-7]` or `realtime[:0-7]` (level defaults to 4).
+pub fn parse_io_class(value: &str) -> Result<IoClass, String> {
+    let (class, level) = value.split_once(':').unwrap_or((value, "4"));
+    let level: u8 = level
+        .parse()
+        .ok()
+        .filter(|l| *l <= 7)
+        .ok_or_else(|| format!("invalid I/O priority level {:?}", level))?;
+    match class.to_lowercase().as_str() {
+        "idle" => Ok(IoClass::Idle),
+        "best-effort" | "besteffort" => Ok(IoClass::BestEffort(level)),
+        "realtime" => Ok(IoClass::Realtime(level)),
+        _ => Err(format!("invalid I/O class {:?}", class)),
+    }
+}
+
+/// Parses a CPU list like `0-3,6`.
+pub fn parse_cpu_list(value: &str) -> Result<Vec<usize>, String> {
+    let mut cpus = Vec::new();
+    for part in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let parse = |s: &str| s.trim().parse::<usize>().map_err(|_| format!("invalid CPU {:?}", s));
+        match part.split_once('-') {
+            Some((first, last)) => cpus.extend(parse(first)?..=parse(last)?),
+            None => cpus.push(parse(part)?),
+        }
+    }
+    if cpus.is_empty() || cpus.iter().any(|&cpu| cpu >= libc::CPU_SETSIZE as usize) {
+        return Err(format!("invalid CPU list {:?}", value));
+    }
+    Ok(cpus)
+}
+
+/// Parses a byte size with an optional K, M, G or T suffix (powers of 1024).
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, shift) = match value.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&value[..value.len() - 1], 10),
+        Some('M') => (&value[..value.len() - 1], 20),
+        Some('G') => (&value[..value.len() - 1], 30),
+        Some('T') => (&value[..value.len() - 1], 40),
+        _ => (value, 0),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("invalid size {:?}", value))
+}
+
+/// `cpu.max` contents allowing `cpus` CPUs' worth of time.
+fn cpu_max(cpus: f64) -> String {
+    format!("{} {}", (cpus * CPU_MAX_PERIOD_US as f64).round() as u64, CPU_MAX_PERIOD_US)
+}
+
+/// Moves this process (and so every ffmpeg it starts) into the cgroup v2 group
+/// `name` under /sys/fs/cgroup, creating it and setting its CPU and memory quota.
+/// Needs a delegated or writable cgroup hierarchy.
+pub fn join_cgroup(name: &str, cpus: Option<f64>, memory: Option<u64>) -> io::Result<PathBuf> {
+    join_cgroup_at(Path::new(CGROUP_ROOT), name, cpus, memory)
+}
+
+fn join_cgroup_at(root: &Path, name: &str, cpus: Option<f64>, memory: Option<u64>) -> io::Result<PathBuf> {
+    let dir = root.join(name.trim_start_matches('/'));
+    fs::create_dir_all(&dir)?;
+
+    // Best effort: the controllers may already be enabled, or be managed by systemd
+    if let Some(parent) = dir.parent() {
+        let _ = fs::write(parent.join("cgroup.subtree_control"), "+cpu +memory");
+    }
+    if let Some(cpus) = cpus {
+        fs::write(dir.join("cpu.max"), cpu_max(cpus))?;
+    }
+    if let Some(memory) = memory {
+        fs::write(dir.join("memory.max"), memory.to_string())?;
+    }
+    fs::write(dir.join("cgroup.procs"), std::process::id().to_string())?;
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_settings() {
+        assert_eq!(parse_io_class("idle"), Ok(IoClass::Idle));
+        assert_eq!(parse_io_class("best-effort:7"), Ok(IoClass::BestEffort(7)));
+        assert_eq!(parse_io_class("realtime"), Ok(IoClass::Realtime(4)));
+        assert!(parse_io_class("best-effort:9").is_err());
+
+        assert_eq!(parse_cpu_list("0-3, 6"), Ok(vec![0, 1, 2, 3, 6]));
+        assert!(parse_cpu_list("a-b").is_err());
+
+        assert_eq!(parse_size("4G"), Ok(4 << 30));
+        assert_eq!(parse_size("512m"), Ok(512 << 20));
+        assert_eq!(parse_size("1000"), Ok(1000));
+        assert!(parse_size("lots").is_err());
+        assert_eq!(cpu_max(2.5), "250000 100000");
+    }
+
+    #[test]
+    fn test_limits_reach_the_child() {
+        let limits = ProcessLimits {
+            nice: Some(15),
+            io_class: Some(IoClass::Idle),
+            cpus: Some(vec![0]),
+            threads: None,
+        };
+        let mut command = Command::new("sh");
+        command.args(["-c", "cut -d' ' -f19 /proc/self/stat; grep Cpus_allowed_list /proc/self/status"]);
+        limits.apply(&mut command);
+
+        let output = command.output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut lines = stdout.lines();
+        assert_eq!(lines.next(), Some("15"));
+        assert_eq!(lines.next().map(|l| l.split_whitespace().last()), Some(Some("0")));
+    }
+
+    #[test]
+    fn test_join_cgroup_writes_quota() {
+        let root = std::env::temp_dir().join("test_cgroup_root");
+        let _ = fs::remove_dir_all(&root);
+
+        let dir = join_cgroup_at(&root, "video_transcoder", Some(2.0), Some(1 << 30)).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("cpu.max")).unwrap(), "200000 100000");
+        assert_eq!(fs::read_to_string(dir.join("memory.max")).unwrap(), "1073741824");
+        assert_eq!(fs::read_to_string(dir.join("cgroup.procs")).unwrap(), std::process::id().to_string());
+        assert_eq!(fs::read_to_string(root.join("cgroup.subtree_control")).unwrap(), "+cpu +memory");
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::ledger::mark_interrupted;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long killed jobs get to unwind (and clean up after themselves) before
+/// leftovers are removed from here.
+const UNWIND_TIMEOUT: Duration = Duration::from_secs(10);
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static NEXT_JOB_ID: AtomicU64 = AtomicU64::new(0);
+static JOBS: Mutex<Option<HashMap<u64, JobState>>> = Mutex::new(None);
+
+#[derive(Debug, Default)]
+struct JobState {
+    input: PathBuf,
+    /// Process groups of the job's running ffmpegs (several when encoding in chunks).
+    ffmpeg: Vec<u32>,
+    /// Temp dirs and partial outputs to remove if the job can't clean up itself.
+    cleanup: Vec<PathBuf>,
+}
+
+fn with_jobs<T>(f: impl FnOnce(&mut HashMap<u64, JobState>) -> T) -> T {
+    let mut guard = JOBS.lock().unwrap_or_else(|e| e.into_inner());
+    f(guard.get_or_insert_with(HashMap::new))
+}
+
+/// True once SIGINT or SIGTERM has been received; no new work should start.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// A running job, known to the shutdown handler until dropped.
+#[derive(Debug)]
+pub struct ActiveJob {
+    id: u64,
+}
+
+pub fn register_job(input: &Path) -> ActiveJob {
+    let id = NEXT_JOB_ID.fetch_add(1, Ordering::SeqCst);
+    with_jobs(|jobs| jobs.insert(id, JobState { input: input.to_path_buf(), ..JobState::default() }));
+    ActiveJob { id }
+}
+
+impl ActiveJob {
+    /// Records the process group of the job's ffmpeg (spawned with `process_group(0)`).
+    pub fn ffmpeg_started(&self, pid: u32) {
+        self.update(|job| job.ffmpeg.push(pid));
+    }
+
+    pub fn ffmpeg_finished(&self, pid: u32) {
+        self.update(|job| job.ffmpeg.retain(|p| *p != pid));
+    }
+
+    /// Removes `path` (file or directory) at shutdown if the job is still registered then.
+    pub fn cleanup_on_exit(&self, path: &Path) {
+        self.update(|job| job.cleanup.push(path.to_path_buf()));
+    }
+
+    fn update(&self, f: impl FnOnce(&mut JobState)) {
+        with_jobs(|jobs| jobs.get_mut(&self.id).map(f));
+    }
+}
+
+impl Drop for ActiveJob {
+    fn drop(&mut self) {
+        with_jobs(|jobs| jobs.remove(&self.id));
+    }
+}
+
+fn active_jobs() -> usize {
+    with_jobs(|jobs| jobs.len())
+}
+
+/// Handles SIGINT and SIGTERM on a background thread: stop taking work, let
+/// running jobs finish within `grace`, then stop them and exit.
+pub fn install(grace: Duration) {
+    let mut signals = match Signals::new([SIGINT, SIGTERM]) {
+        Ok(signals) => signals,
+        Err(e) => {
+            println!("⚠️ Failed to install signal handlers: {}", e);
+            return;
+        }
+    };
+    thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            REQUESTED.store(true, Ordering::SeqCst);
+            println!("🛑 Received signal {}, shutting down", signal);
+            let interrupted = drain(grace, UNWIND_TIMEOUT);
+            if !interrupted.is_empty() {
+                println!("💾 {} interrupted job(s) will resume on the next start", interrupted.len());
+            }
+            std::process::exit(128 + signal);
+        }
+    });
+}
+
+/// Waits up to `grace` for running jobs. Jobs still running after that are
+/// recorded as interrupted and their ffmpeg process groups killed; whatever they
+/// haven't cleaned up within `unwind` is removed here. Returns the interrupted inputs.
+fn drain(grace: Duration, unwind: Duration) -> Vec<PathBuf> {
+    let running = active_jobs();
+    if running > 0 {
+        println!("⏳ Waiting up to {}s for {} running job(s)", grace.as_secs(), running);
+    }
+    wait_for_jobs(grace);
+
+    let interrupted: Vec<PathBuf> = with_jobs(|jobs| {
+        jobs.values()
+            .map(|job| {
+                if let Some(stem) = job.input.file_stem() {
+                    mark_interrupted(&stem.to_string_lossy());
+                }
+                for &pid in &job.ffmpeg {
+                    // SAFETY: killpg only sends a signal; the group id is an ffmpeg we spawned
+                    unsafe {
+                        libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+                    }
+                }
+                job.input.clone()
+            })
+            .collect()
+    });
+    if interrupted.is_empty() {
+        return interrupted;
+    }
+
+    println!("⏹️ Stopped {} job(s) after the grace period", interrupted.len());
+    wait_for_jobs(unwind);
+    let leftovers: Vec<PathBuf> = with_jobs(|jobs| jobs.drain().flat_map(|(_, job)| job.cleanup).collect());
+    for path in leftovers {
+        let result = if path.is_dir() { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+        if result.is_ok() {
+            println!("🧹 Removed {}", path.display());
+        }
+    }
+    interrupted
+}
+
+fn wait_for_jobs(timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    while active_jobs() > 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::{clear_interrupted, load_interrupted};
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    #[test]
+    fn test_drain_stops_jobs_and_cleans_up() {
+        let dir = std::env::temp_dir().join("test_shutdown_drain");
+        fs::create_dir_all(&dir).unwrap();
+        let partial = dir.join(".interrupted.mp4.tmp");
+        fs::write(&partial, b"half").unwrap();
+
+        let mut ffmpeg = Command::new("sleep").arg("30").process_group(0).spawn().unwrap();
+        let job = register_job(Path::new("/watch/test_shutdown_interrupted.mkv"));
+        job.ffmpeg_started(ffmpeg.id());
+        job.cleanup_on_exit(&dir);
+        // This job never unwinds on its own, so drain has to clean up after it
+        std::mem::forget(job);
+
+        let start = Instant::now();
+        let interrupted = drain(Duration::from_millis(200), Duration::from_millis(200));
+        assert_eq!(interrupted, vec![PathBuf::from("/watch/test_shutdown_interrupted.mkv")]);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(!ffmpeg.wait().unwrap().success());
+        assert!(!dir.exists());
+
+        assert!(load_interrupted().contains("test_shutdown_interrupted"));
+        clear_interrupted("test_shutdown_interrupted");
+    }
+}
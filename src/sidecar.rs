@@ -0,0 +1,191 @@
+use crate::subtitles::SubtitleOffset;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Per-file options live next to the source as `<stem>.transcode`.
+pub const SIDECAR_EXTENSION: &str = "transcode";
+/// No real subtitle is off by more than a day; anything beyond is a typo.
+const MAX_OFFSET_SECS: f64 = 86_400.0;
+
+/// Per-file overrides read from a sidecar, e.g.
+///
+/// ```text
+/// # Movie.transcode
+/// subtitle_offset = -2.5      # seconds, or "auto"
+/// subtitle_fps = 23.976:25    # authored fps : video fps
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FileOptions {
+    pub subtitle_offset: Option<SubtitleOffset>,
+    pub subtitle_fps: Option<(f64, f64)>,
+}
+
+impl FileOptions {
+    pub fn has_subtitle_timing(&self) -> bool {
+        self.subtitle_offset.is_some() || self.subtitle_fps.is_some()
+    }
+}
+
+pub fn sidecar_path(input: &Path) -> PathBuf {
+    input.with_extension(SIDECAR_EXTENSION)
+}
+
+/// Loads the sidecar for `input`. A missing sidecar yields default options;
+/// unreadable lines are reported and skipped so one typo doesn't block the job.
+pub fn load_file_options(input: &Path) -> FileOptions {
+    let path = sidecar_path(input);
+    match fs::read_to_string(&path) {
+        Ok(text) => {
+            let (options, errors) = parse_file_options(&text);
+            for e in errors {
+                println!("⚠️ {}: {}", path.display(), e);
+            }
+            options
+        }
+        Err(_) => FileOptions::default(),
+    }
+}
+
+pub fn save_file_options(input: &Path, options: &FileOptions) -> io::Result<()> {
+    let mut text = String::new();
+    match options.subtitle_offset {
+        Some(SubtitleOffset::Auto) => text.push_str("subtitle_offset = auto\n"),
+        Some(SubtitleOffset::Fixed(ms)) => {
+            text.push_str(&format!("subtitle_offset = {}\n", ms as f64 / 1000.0))
+        }
+        None => {}
+    }
+    if let Some((from, to)) = options.subtitle_fps {
+        text.push_str(&format!("subtitle_fps = {}:{}\n", from, to));
+    }
+    fs::write(sidecar_path(input), text)
+}
+
+pub fn parse_file_options(text: &str) -> (FileOptions, Vec<String>) {
+    let mut options = FileOptions::default();
+    let mut errors = Vec::new();
+
+    for (line, key, value) in parse_kv(text) {
+        let result = match key {
+            "subtitle_offset" => parse_offset(value).map(|o| options.subtitle_offset = Some(o)),
+            "subtitle_fps" => parse_fps_pair(value).map(|f| options.subtitle_fps = Some(f)),
+            _ => Err(format!("unknown key {:?}", key)),
+        };
+        if let Err(e) = result {
+            errors.push(format!("line {}: {}", line, e));
+        }
+    }
+
+    (options, errors)
+}
+
+/// Splits `key = value` lines, skipping blanks and comments. A `#` starts a
+/// comment at the start of a line or after whitespace, outside double quotes; a
+/// value wrapped in double quotes is taken without them, so
+/// `global_tags = "rewrite comment=Episode #1"` keeps its `#`.
+/// Yields `(1-based line, key, value)`.
+pub fn parse_kv(text: &str) -> Vec<(usize, &str, &str)> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(n, line)| {
+            let line = strip_comment(line).trim();
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            Some((n + 1, key.trim(), value))
+        })
+        .collect()
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut previous: Option<char> = None;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted && previous.is_none_or(char::is_whitespace) => return &line[..i],
+            _ => {}
+        }
+        previous = Some(c);
+    }
+    line
+}
+
+pub fn parse_offset(value: &str) -> Result<SubtitleOffset, String> {
+    if value.eq_ignore_ascii_case("auto") {
+        return Ok(SubtitleOffset::Auto);
+    }
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|s| s.is_finite() && s.abs() <= MAX_OFFSET_SECS)
+        .map(|s| SubtitleOffset::Fixed((s * 1000.0).round() as i64))
+        .ok_or_else(|| format!("invalid offset {:?} (expected seconds up to a day, or \"auto\")", value))
+}
+
+pub fn parse_fps_pair(value: &str) -> Result<(f64, f64), String> {
+    let invalid = || format!("invalid fps {:?} (expected FROM:TO, e.g. 23.976:25)", value);
+    let (from, to) = value.split_once(':').ok_or_else(invalid)?;
+    let from: f64 = from.trim().parse().map_err(|_| invalid())?;
+    let to: f64 = to.trim().parse().map_err(|_| invalid())?;
+    // nan and inf parse fine but would collapse every cue to 0 or infinity
+    if !(from.is_finite() && to.is_finite() && from > 0.0 && to > 0.0) {
+        return Err(invalid());
+    }
+    Ok((from, to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_file_options() {
+        let text = "# sync fixes\nsubtitle_offset = -2.5\nsubtitle_fps = 23.976:25 # PAL\nbogus = 1\n";
+        let (options, errors) = parse_file_options(text);
+
+        assert_eq!(options.subtitle_offset, Some(SubtitleOffset::Fixed(-2_500)));
+        assert_eq!(options.subtitle_fps, Some((23.976, 25.0)));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("line 4"));
+    }
+
+    #[test]
+    fn test_parse_kv_keeps_hashes_inside_values() {
+        let text = "# comment\ntitle = Episode#1 # trailing\ntags = \"rewrite comment=Episode #1\"\n  # indented\n";
+        assert_eq!(
+            parse_kv(text),
+            vec![(2, "title", "Episode#1"), (3, "tags", "rewrite comment=Episode #1")]
+        );
+    }
+
+    #[test]
+    fn test_rejects_non_finite_values() {
+        assert_eq!(parse_fps_pair("25:23.976"), Ok((25.0, 23.976)));
+        for value in ["nan:25", "25:inf", "-inf:25", "0:25"] {
+            assert!(parse_fps_pair(value).is_err(), "{}", value);
+        }
+        assert!(parse_offset("inf").is_err());
+        assert!(parse_offset("NaN").is_err());
+        assert!(parse_offset("1e300").is_err());
+        assert_eq!(parse_offset("-86400"), Ok(SubtitleOffset::Fixed(-86_400_000)));
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let input = std::env::temp_dir().join("test_sidecar_round_trip.mkv");
+        let options = FileOptions {
+            subtitle_offset: Some(SubtitleOffset::Auto),
+            subtitle_fps: Some((25.0, 23.976)),
+        };
+
+        save_file_options(&input, &options).unwrap();
+        assert_eq!(load_file_options(&input), options);
+
+        let _ = fs::remove_file(sidecar_path(&input));
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+
+/// Default concurrent jobs per backend: consumer NVIDIA cards cap NVENC sessions,
+/// VAAPI saturates after a couple of jobs, and libx264 wants the whole CPU.
+const DEFAULT_LIMITS: [(&str, usize); 3] = [("nvenc", 3), ("vaapi", 2), ("cpu", 1)];
+
+/// One encoder: a backend plus which of its devices (a render node for VAAPI, a
+/// CUDA index for NVENC, empty for CPU).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Device {
+    pub backend: &'static str,
+    pub id: String,
+}
+
+impl Device {
+    pub fn cpu() -> Self {
+        Device { backend: "cpu", id: String::new() }
+    }
+}
+
+/// Concurrency limits, parsed from `SLOT_LIMITS` such as
+/// `nvenc=3,vaapi=2,cpu=1,vaapi:/dev/dri/renderD129=1`. A backend limit caps all
+/// of that backend's devices together; a `backend:device` limit caps one device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotLimits {
+    backends: HashMap<String, usize>,
+    devices: HashMap<(String, String), usize>,
+}
+
+impl Default for SlotLimits {
+    fn default() -> Self {
+        SlotLimits {
+            backends: DEFAULT_LIMITS.iter().map(|(b, n)| (b.to_string(), *n)).collect(),
+            devices: HashMap::new(),
+        }
+    }
+}
+
+impl SlotLimits {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut limits = SlotLimits::default();
+        for item in spec.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=limit, got {:?}", item))?;
+            let limit = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid limit {:?} for {}", value, key))?;
+            match key.trim().split_once(':') {
+                Some((backend, device)) => limits.devices.insert((backend.into(), device.into()), limit),
+                None => limits.backends.insert(key.trim().into(), limit),
+            };
+        }
+        Ok(limits)
+    }
+
+    fn backend(&self, backend: &str) -> usize {
+        self.backends.get(backend).copied().unwrap_or(1)
+    }
+
+    fn device(&self, device: &Device) -> usize {
+        let key = (device.backend.to_string(), device.id.clone());
+        self.devices.get(&key).copied().unwrap_or_else(|| self.backend(device.backend))
+    }
+}
+
+/// Jobs running per device, shared by all workers.
+static IN_USE: Mutex<Option<HashMap<Device, usize>>> = Mutex::new(None);
+static RELEASED: Condvar = Condvar::new();
+
+/// A held encoder slot; released when dropped.
+#[derive(Debug)]
+pub struct Slot {
+    pub device: Device,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut guard = IN_USE.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = guard.get_or_insert_with(HashMap::new).get_mut(&self.device) {
+            *count = count.saturating_sub(1);
+        }
+        RELEASED.notify_all();
+    }
+}
+
+/// Takes a slot on the least busy GPU device in `gpus` that has room, or on the
+/// CPU if `cpu_overflow` and every GPU is full. Waits until one frees up.
+pub fn acquire(limits: &SlotLimits, gpus: &[Device], cpu_overflow: bool) -> Slot {
+    let cpu = Device::cpu();
+    let mut candidates: Vec<&Device> = gpus.iter().collect();
+    if cpu_overflow || candidates.is_empty() {
+        candidates.push(&cpu);
+    }
+
+    let mut guard = IN_USE.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        let in_use = guard.get_or_insert_with(HashMap::new);
+        let load = |device: &Device| in_use.get(device).copied().unwrap_or(0);
+        let backend_load =
+            |backend: &str| in_use.iter().filter(|(d, _)| d.backend == backend).map(|(_, n)| n).sum::<usize>();
+
+        let free = candidates
+            .iter()
+            .filter(|d| load(d) < limits.device(d) && backend_load(d.backend) < limits.backend(d.backend))
+            .min_by_key(|d| (d.backend == "cpu", load(d)));
+        if let Some(device) = free {
+            let device = (*device).clone();
+            *in_use.entry(device.clone()).or_insert(0) += 1;
+            return Slot { device };
+        }
+        guard = RELEASED.wait(guard).unwrap_or_else(|e| e.into_inner());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    // Each test uses its own backend names, since the counters are process-wide
+    fn gpu(backend: &'static str, id: &str) -> Device {
+        Device { backend, id: id.into() }
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = SlotLimits::parse("nvenc=5, vaapi:/dev/dri/renderD129=1").unwrap();
+        assert_eq!(limits.backend("nvenc"), 5);
+        assert_eq!(limits.backend("vaapi"), 2);
+        assert_eq!(limits.device(&gpu("vaapi", "/dev/dri/renderD129")), 1);
+        assert_eq!(limits.device(&gpu("vaapi", "/dev/dri/renderD128")), 2);
+        assert!(SlotLimits::parse("nvenc").is_err());
+        assert!(SlotLimits::parse("nvenc=lots").is_err());
+    }
+
+    #[test]
+    fn test_spreads_over_devices_then_overflows_to_cpu() {
+        let limits = SlotLimits::parse("spread=3,spread:a=1,cpu=8").unwrap();
+        let gpus = [gpu("spread", "a"), gpu("spread", "b")];
+
+        let first = acquire(&limits, &gpus, true);
+        let second = acquire(&limits, &gpus, true);
+        let third = acquire(&limits, &gpus, true);
+        let fourth = acquire(&limits, &gpus, true);
+        let ids: Vec<&str> = [&first, &second, &third].iter().map(|s| s.device.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b", "b"]);
+        // The backend cap of 3 is reached even though device b alone would allow more
+        assert_eq!(fourth.device, Device::cpu());
+    }
+
+    #[test]
+    fn test_waits_for_a_slot_without_overflow() {
+        let limits = SlotLimits::parse("wait=1").unwrap();
+        let gpus = vec![gpu("wait", "0")];
+        let held = acquire(&limits, &gpus, false);
+
+        let (tx, rx) = mpsc::channel();
+        let waiter = thread::spawn(move || {
+            let slot = acquire(&limits, &gpus, false);
+            tx.send(slot.device.clone()).unwrap();
+        });
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+
+        drop(held);
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), gpu("wait", "0"));
+        waiter.join().unwrap();
+    }
+}
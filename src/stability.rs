@@ -0,0 +1,220 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stability {
+    /// Unchanged for the whole interval and nobody is writing to or locking it.
+    Stable,
+    /// Still changing, recently modified, or held open for writing.
+    Settling,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Observation {
+    size: u64,
+    modified: Option<SystemTime>,
+    since: Instant,
+}
+
+static OBSERVED: Mutex<Option<HashMap<PathBuf, Observation>>> = Mutex::new(None);
+
+/// Files currently held open for writing, and locked (device, inode) pairs, as
+/// visible through /proc. Taken once per scan since walking /proc isn't free.
+#[derive(Debug, Default)]
+pub struct Activity {
+    writers: HashSet<PathBuf>,
+    locks: HashSet<(u64, u64)>,
+}
+
+impl Activity {
+    pub fn snapshot() -> Self {
+        Activity {
+            writers: open_writers(),
+            locks: fs::read_to_string("/proc/locks")
+                .map(|text| parse_locks(&text))
+                .unwrap_or_default(),
+        }
+    }
+
+    fn is_busy(&self, path: &Path, metadata: &fs::Metadata) -> bool {
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let dev = metadata.dev();
+        let device = (u64::from(libc::major(dev)) << 32) | u64::from(libc::minor(dev));
+        self.writers.contains(&canonical) || self.locks.contains(&(device, metadata.ino()))
+    }
+}
+
+/// Decides whether `path` has finished arriving. Size and mtime must be unchanged
+/// across scans for at least `interval`, and no local process may have it open for
+/// writing or locked. Once a file has been seen unchanged by two scans, an mtime
+/// already older than `interval` counts as quiet from then; a first sighting never
+/// does, since `cp -p` and rsync keep the old mtime while still writing.
+pub fn check(path: &Path, interval: Duration, activity: &Activity) -> Stability {
+    let Ok(metadata) = fs::metadata(path) else {
+        return Stability::Settling;
+    };
+    let now = Instant::now();
+    let observed_now = Observation {
+        size: metadata.len(),
+        modified: metadata.modified().ok(),
+        since: now,
+    };
+
+    let mut guard = OBSERVED.lock().unwrap_or_else(|e| e.into_inner());
+    let observed = guard.get_or_insert_with(HashMap::new);
+    let unchanged_since = match observed.get(path) {
+        Some(o) if o.size == observed_now.size && o.modified == observed_now.modified => o.since,
+        _ => {
+            observed.insert(path.to_path_buf(), observed_now);
+            return Stability::Settling;
+        }
+    };
+    drop(guard);
+
+    let age = observed_now
+        .modified
+        .and_then(|m| SystemTime::now().duration_since(m).ok())
+        .unwrap_or_default();
+    let quiet_for = now.duration_since(unchanged_since).max(age);
+
+    if quiet_for < interval || activity.is_busy(path, &metadata) {
+        Stability::Settling
+    } else {
+        Stability::Stable
+    }
+}
+
+/// Drops the remembered observation once a file has been handled.
+pub fn forget(path: &Path) {
+    let mut guard = OBSERVED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(observed) = guard.as_mut() {
+        observed.remove(path);
+    }
+}
+
+fn open_writers() -> HashSet<PathBuf> {
+    let mut writers = HashSet::new();
+    let Ok(procs) = fs::read_dir("/proc") else {
+        return writers;
+    };
+
+    for proc_entry in procs.flatten() {
+        let pid_dir = proc_entry.path();
+        if !proc_entry.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+        // Other users' processes are unreadable unless we run as root; skip them
+        let Ok(fds) = fs::read_dir(pid_dir.join("fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            let Ok(target) = fs::read_link(fd.path()) else {
+                continue;
+            };
+            if !target.is_absolute() {
+                continue;
+            }
+            let fdinfo = pid_dir.join("fdinfo").join(fd.file_name());
+            if fs::read_to_string(fdinfo).is_ok_and(|info| fdinfo_is_writer(&info)) {
+                writers.insert(target);
+            }
+        }
+    }
+    writers
+}
+
+/// True when an fdinfo `flags:` field (octal) has write access.
+fn fdinfo_is_writer(fdinfo: &str) -> bool {
+    fdinfo
+        .lines()
+        .find_map(|l| l.strip_prefix("flags:"))
+        .and_then(|flags| i64::from_str_radix(flags.trim(), 8).ok())
+        .is_some_and(|flags| flags & i64::from(libc::O_ACCMODE) != i64::from(libc::O_RDONLY))
+}
+
+/// Parses /proc/locks lines like `1: POSIX  ADVISORY  WRITE 1234 08:01:5678 0 EOF`
+/// into `(major << 32 | minor, inode)` pairs.
+fn parse_locks(text: &str) -> HashSet<(u64, u64)> {
+    text.lines()
+        .filter(|l| !l.contains("->")) // blocked waiters repeat an existing lock
+        .filter_map(|line| {
+            let id = line.split_whitespace().nth(5)?;
+            let mut parts = id.split(':');
+            let major = u64::from_str_radix(parts.next()?, 16).ok()?;
+            let minor = u64::from_str_radix(parts.next()?, 16).ok()?;
+            let inode = parts.next()?.parse().ok()?;
+            Some(((major << 32) | minor, inode))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+
+    #[test]
+    fn test_changing_file_is_settling_until_quiet() {
+        let path = std::env::temp_dir().join("test_stability_settling.mkv");
+        fs::write(&path, b"part").unwrap();
+        let quiet = Activity::default();
+
+        assert_eq!(check(&path, Duration::ZERO, &quiet), Stability::Settling);
+        assert_eq!(check(&path, Duration::from_secs(3600), &quiet), Stability::Settling);
+        assert_eq!(check(&path, Duration::ZERO, &quiet), Stability::Stable);
+
+        // A copy keeping an old mtime is still settling while it grows
+        let backdate = |path: &Path| {
+            let file = File::options().append(true).open(path).unwrap();
+            file.set_modified(SystemTime::now() - Duration::from_secs(120)).unwrap();
+            file
+        };
+        backdate(&path);
+        assert_eq!(check(&path, Duration::from_secs(60), &quiet), Stability::Settling);
+        backdate(&path).write_all(b" more").unwrap();
+        backdate(&path);
+        assert_eq!(check(&path, Duration::from_secs(60), &quiet), Stability::Settling);
+
+        // Seen unchanged twice, the old mtime counts as already quiet
+        assert_eq!(check(&path, Duration::from_secs(60), &quiet), Stability::Stable);
+
+        forget(&path);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_open_writer_keeps_file_settling() {
+        let path = std::env::temp_dir().join("test_stability_writer.mkv");
+        let mut writer = File::create(&path).unwrap();
+        writer.write_all(b"still downloading").unwrap();
+
+        assert_eq!(check(&path, Duration::ZERO, &Activity::snapshot()), Stability::Settling);
+        assert_eq!(check(&path, Duration::ZERO, &Activity::snapshot()), Stability::Settling);
+        drop(writer);
+        assert_eq!(check(&path, Duration::ZERO, &Activity::snapshot()), Stability::Stable);
+
+        forget(&path);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_parse_locks_and_fdinfo() {
+        let locks = parse_locks(
+            "1: POSIX  ADVISORY  WRITE 1234 08:01:5678 0 EOF\n\
+             1: -> POSIX  ADVISORY  WRITE 1300 08:01:5678 0 EOF\n\
+             2: FLOCK  ADVISORY  WRITE 99 00:2a:42 0 EOF\n",
+        );
+        assert_eq!(locks.len(), 2);
+        assert!(locks.contains(&((8 << 32) | 1, 5678)));
+        assert!(locks.contains(&(0x2a, 42)));
+
+        assert!(fdinfo_is_writer("pos:\t0\nflags:\t0100001\nmnt_id:\t25\n"));
+        assert!(fdinfo_is_writer("pos:\t0\nflags:\t02\n"));
+        assert!(!fdinfo_is_writer("pos:\t0\nflags:\t0100000\n"));
+    }
+}
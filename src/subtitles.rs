@@ -0,0 +1,598 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Minimum display time given to a cue whose end timestamp had to be repaired.
+const MIN_CUE_MS: u64 = 1000;
+
+/// Search window and resolution for the automatic offset estimate.
+const AUTO_OFFSET_RANGE_MS: i64 = 10_000;
+const AUTO_OFFSET_STEP_MS: i64 = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub index: usize,
+    /// 1-based line in the source file where the cue's block starts.
+    pub line: usize,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+}
+
+/// A problem found while parsing or validating an SRT file.
+///
+/// `line` is the 1-based line where the offending block starts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SrtIssue {
+    BrokenTimestamp { line: usize, raw: String },
+    InvertedTiming { line: usize },
+    EmptyCue { line: usize },
+    OutOfOrderNumbering { line: usize, expected: usize, found: Option<usize> },
+    OutOfOrderTiming { line: usize },
+    Overlap { line: usize, overlap_ms: u64 },
+}
+
+impl fmt::Display for SrtIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SrtIssue::BrokenTimestamp { line, raw } => {
+                write!(f, "line {}: broken timestamp {:?} (cue dropped)", line, raw)
+            }
+            SrtIssue::InvertedTiming { line } => write!(f, "line {}: cue ends before it starts", line),
+            SrtIssue::EmptyCue { line } => write!(f, "line {}: empty cue (dropped)", line),
+            SrtIssue::OutOfOrderNumbering { line, expected, found } => match found {
+                Some(n) => write!(f, "line {}: cue numbered {} but expected {}", line, n, expected),
+                None => write!(f, "line {}: cue number missing, expected {}", line, expected),
+            },
+            SrtIssue::OutOfOrderTiming { line } => {
+                write!(f, "line {}: cue starts before the previous one", line)
+            }
+            SrtIssue::Overlap { line, overlap_ms } => {
+                write!(f, "line {}: cue overlaps the previous one by {}ms", line, overlap_ms)
+            }
+        }
+    }
+}
+
+/// How far to move subtitles in time, either a fixed amount or estimated from the audio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubtitleOffset {
+    Fixed(i64),
+    Auto,
+}
+
+/// Result of validating and repairing an SRT file on disk.
+#[derive(Debug)]
+pub struct SrtReport {
+    pub cues: usize,
+    pub issues: Vec<SrtIssue>,
+}
+
+/// Parses SRT text into cues, collecting every issue found along the way.
+///
+/// Parsing is lenient: `.` is accepted as the millisecond separator, missing
+/// hours and short fields are tolerated, and blocks whose timing line can't be
+/// read at all are dropped and reported rather than failing the whole file.
+pub fn parse_srt(text: &str) -> (Vec<Cue>, Vec<SrtIssue>) {
+    let mut cues = Vec::new();
+    let mut issues = Vec::new();
+    let mut block: Vec<&str> = Vec::new();
+    let mut block_line = 0;
+    let mut numbered = 0;
+
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    for (n, line) in text.lines().chain(std::iter::once("")).enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            if !block.is_empty() {
+                parse_block(&block, block_line, &mut numbered, &mut cues, &mut issues);
+                block.clear();
+            }
+            continue;
+        }
+        if block.is_empty() {
+            block_line = n + 1;
+        }
+        block.push(line);
+    }
+
+    (cues, issues)
+}
+
+fn parse_block(
+    block: &[&str],
+    line: usize,
+    numbered: &mut usize,
+    cues: &mut Vec<Cue>,
+    issues: &mut Vec<SrtIssue>,
+) {
+    let Some(timing_pos) = block.iter().position(|l| l.contains("-->")) else {
+        // A block without a timing line is most likely a stray line of a previous
+        // cue's text separated by a blank line; keep it with that cue.
+        if let Some(prev) = cues.last_mut() {
+            prev.text.push('\n');
+            prev.text.push_str(&block.join("\n"));
+        } else {
+            issues.push(SrtIssue::BrokenTimestamp { line, raw: block[0].to_string() });
+        }
+        return;
+    };
+
+    let expected = *numbered + 1;
+    let found = block[..timing_pos]
+        .last()
+        .and_then(|l| l.trim().parse::<usize>().ok());
+    if found != Some(expected) {
+        issues.push(SrtIssue::OutOfOrderNumbering { line, expected, found });
+    }
+    *numbered = found.unwrap_or(expected);
+
+    let raw = block[timing_pos];
+    let Some((start_ms, end_ms)) = parse_timing(raw) else {
+        issues.push(SrtIssue::BrokenTimestamp { line, raw: raw.trim().to_string() });
+        return;
+    };
+
+    let text = block[timing_pos + 1..].join("\n");
+    if text.trim().is_empty() {
+        issues.push(SrtIssue::EmptyCue { line });
+        return;
+    }
+
+    cues.push(Cue { index: expected, line, start_ms, end_ms, text });
+}
+
+fn parse_timing(raw: &str) -> Option<(u64, u64)> {
+    let (start, rest) = raw.split_once("-->")?;
+    // Anything after the end timestamp (e.g. "X1:... Y1:..." position hints) is ignored
+    let end = rest.split_whitespace().next()?;
+    Some((parse_timestamp(start.trim())?, parse_timestamp(end)?))
+}
+
+/// Parses `HH:MM:SS,mmm`, tolerating `.` as separator, missing hours and short fields.
+pub fn parse_timestamp(raw: &str) -> Option<u64> {
+    let (clock, millis) = match raw.rsplit_once([',', '.']) {
+        Some((clock, millis)) => (clock, millis),
+        None => (raw, "0"),
+    };
+    if millis.is_empty() || millis.len() > 3 || !millis.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    // "5" after the separator means 500ms, not 5ms
+    let millis: u64 = format!("{:0<3}", millis).parse().ok()?;
+
+    let fields: Vec<u64> = clock
+        .split(':')
+        .map(|f| f.trim().parse::<u64>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let (h, m, s) = match fields.as_slice() {
+        [h, m, s] => (*h, *m, *s),
+        [m, s] => (0, *m, *s),
+        _ => return None,
+    };
+    if m >= 60 || s >= 60 {
+        return None;
+    }
+
+    // The hour field is unbounded, so a garbled one must not overflow
+    h.checked_mul(3600)?
+        .checked_add(m * 60 + s)?
+        .checked_mul(1000)?
+        .checked_add(millis)
+}
+
+pub fn format_timestamp(ms: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+/// Repairs parsed cues so ffmpeg accepts them: fixes inverted timings, sorts by
+/// start time, trims overlaps and renumbers sequentially.
+pub fn repair_cues(mut cues: Vec<Cue>) -> (Vec<Cue>, Vec<SrtIssue>) {
+    let mut issues = Vec::new();
+
+    for cue in cues.iter_mut() {
+        if cue.end_ms <= cue.start_ms {
+            issues.push(SrtIssue::InvertedTiming { line: cue.line });
+            cue.end_ms = cue.start_ms + MIN_CUE_MS;
+        }
+    }
+
+    if let Some(w) = cues.windows(2).find(|w| w[1].start_ms < w[0].start_ms) {
+        issues.push(SrtIssue::OutOfOrderTiming { line: w[1].line });
+        cues.sort_by_key(|c| c.start_ms);
+    }
+
+    let mut repaired: Vec<Cue> = Vec::with_capacity(cues.len());
+    for cue in cues {
+        if let Some(prev) = repaired.last_mut() {
+            if prev.end_ms > cue.start_ms {
+                issues.push(SrtIssue::Overlap {
+                    line: cue.line,
+                    overlap_ms: prev.end_ms - cue.start_ms,
+                });
+                // Cues starting together can't be trimmed apart; show them as one
+                if prev.start_ms == cue.start_ms {
+                    prev.text.push('\n');
+                    prev.text.push_str(&cue.text);
+                    prev.end_ms = prev.end_ms.max(cue.end_ms);
+                    continue;
+                }
+                prev.end_ms = cue.start_ms;
+            }
+        }
+        repaired.push(cue);
+    }
+
+    for (i, cue) in repaired.iter_mut().enumerate() {
+        cue.index = i + 1;
+    }
+
+    (repaired, issues)
+}
+
+pub fn write_srt(cues: &[Cue]) -> String {
+    let mut out = String::new();
+    for cue in cues {
+        out.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            cue.index,
+            format_timestamp(cue.start_ms),
+            format_timestamp(cue.end_ms),
+            cue.text
+        ));
+    }
+    out
+}
+
+/// Moves every cue by `offset_ms`. Cues pushed entirely before zero are dropped,
+/// cues straddling zero are clipped.
+pub fn shift_cues(cues: Vec<Cue>, offset_ms: i64) -> Vec<Cue> {
+    let shift = |t: u64| shifted_ms(t, offset_ms).max(0) as u64;
+    let mut shifted: Vec<Cue> = cues
+        .into_iter()
+        .filter(|c| shifted_ms(c.end_ms, offset_ms) > 0)
+        .map(|c| Cue { start_ms: shift(c.start_ms), end_ms: shift(c.end_ms), ..c })
+        .collect();
+    for (i, cue) in shifted.iter_mut().enumerate() {
+        cue.index = i + 1;
+    }
+    shifted
+}
+
+/// `t + offset_ms`, saturating rather than wrapping for extreme values.
+fn shifted_ms(t: u64, offset_ms: i64) -> i64 {
+    i64::try_from(t).unwrap_or(i64::MAX).saturating_add(offset_ms)
+}
+
+/// Retimes cues authored against `from_fps` for a video playing at `to_fps`,
+/// e.g. 23.976 → 25 for PAL speed-up releases.
+pub fn rescale_cues(cues: Vec<Cue>, from_fps: f64, to_fps: f64) -> Vec<Cue> {
+    let ratio = from_fps / to_fps;
+    let scale = |t: u64| (t as f64 * ratio).round() as u64;
+    cues.into_iter()
+        .map(|c| Cue { start_ms: scale(c.start_ms), end_ms: scale(c.end_ms), ..c })
+        .collect()
+}
+
+/// Finds speech in the first audio stream of `media` by inverting ffmpeg's
+/// `silencedetect` output. Returns `(start_ms, end_ms)` intervals.
+pub fn detect_speech(media: &Path) -> io::Result<Vec<(u64, u64)>> {
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(media)
+        .args(["-map", "0:a:0", "-af", "silencedetect=noise=-35dB:d=0.4", "-f", "null", "-"])
+        .output()?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        let reason = stderr.lines().last().unwrap_or("unknown error").to_string();
+        return Err(io::Error::other(format!("silencedetect failed: {}", reason)));
+    }
+
+    Ok(speech_from_silencedetect(&stderr))
+}
+
+fn speech_from_silencedetect(stderr: &str) -> Vec<(u64, u64)> {
+    let seconds = |line: &str, key: &str| -> Option<u64> {
+        let value = line.split(key).nth(1)?.split_whitespace().next()?;
+        value.parse::<f64>().ok().map(|s| (s.max(0.0) * 1000.0).round() as u64)
+    };
+
+    let duration_ms = stderr
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("Duration:"))
+        .and_then(|d| parse_timestamp(d.split(',').next()?.trim()));
+
+    let mut speech = Vec::new();
+    let mut speech_start = Some(0);
+    for line in stderr.lines() {
+        if let Some(start) = seconds(line, "silence_start: ") {
+            if let Some(from) = speech_start.take().filter(|from| start > *from) {
+                speech.push((from, start));
+            }
+        } else if let Some(end) = seconds(line, "silence_end: ") {
+            speech_start = Some(end);
+        }
+    }
+    // Audio that ends while still talking leaves the last interval open
+    if let (Some(from), Some(duration)) = (speech_start, duration_ms) {
+        if duration > from {
+            speech.push((from, duration));
+        }
+    }
+    speech
+}
+
+/// Estimates the offset that best lines cues up with speech, by sliding the
+/// cues across `±range_ms` and maximising total overlap with the speech intervals.
+///
+/// Returns `None` when there's nothing to compare against.
+pub fn estimate_offset(cues: &[Cue], speech: &[(u64, u64)], range_ms: i64, step_ms: i64) -> Option<i64> {
+    if cues.is_empty() || speech.is_empty() {
+        return None;
+    }
+
+    let mut best: Option<(u64, i64)> = None;
+    let mut offset = -range_ms;
+    while offset <= range_ms {
+        let score = speech_overlap(cues, speech, offset);
+        // Prefer the smallest correction when several offsets score the same
+        let better = match best {
+            None => true,
+            Some((s, o)) => score > s || (score == s && offset.abs() < o.abs()),
+        };
+        if better {
+            best = Some((score, offset));
+        }
+        offset += step_ms;
+    }
+
+    best.filter(|(score, _)| *score > 0).map(|(_, offset)| offset)
+}
+
+fn speech_overlap(cues: &[Cue], speech: &[(u64, u64)], offset_ms: i64) -> u64 {
+    let mut total = 0;
+    let mut j = 0;
+    for cue in cues {
+        let start = shifted_ms(cue.start_ms, offset_ms);
+        let end = shifted_ms(cue.end_ms, offset_ms);
+        while j < speech.len() && shifted_ms(speech[j].1, 0) <= start {
+            j += 1;
+        }
+        let mut k = j;
+        while k < speech.len() && shifted_ms(speech[k].0, 0) < end {
+            let overlap = end.min(shifted_ms(speech[k].1, 0)) - start.max(shifted_ms(speech[k].0, 0));
+            total += overlap.max(0) as u64;
+            k += 1;
+        }
+    }
+    total
+}
+
+/// Applies framerate conversion and then an offset to an SRT file in place.
+///
+/// `media` is only read when the offset is `Auto`. Returns the offset applied in ms.
+pub fn retime_srt_file(
+    path: &Path,
+    fps: Option<(f64, f64)>,
+    offset: Option<SubtitleOffset>,
+    media: &Path,
+) -> io::Result<i64> {
+    let text = fs::read_to_string(path)?;
+    let (mut cues, _) = parse_srt(&text);
+
+    if let Some((from, to)) = fps {
+        cues = rescale_cues(cues, from, to);
+    }
+
+    let offset_ms = match offset {
+        Some(SubtitleOffset::Fixed(ms)) => ms,
+        Some(SubtitleOffset::Auto) => {
+            let speech = detect_speech(media)?;
+            estimate_offset(&cues, &speech, AUTO_OFFSET_RANGE_MS, AUTO_OFFSET_STEP_MS).unwrap_or(0)
+        }
+        None => 0,
+    };
+    if offset_ms != 0 {
+        cues = shift_cues(cues, offset_ms);
+    }
+
+    fs::write(path, write_srt(&cues))?;
+    Ok(offset_ms)
+}
+
+/// Parses, validates and repairs an (already UTF-8) SRT file in place.
+pub fn repair_srt_file(path: &Path) -> io::Result<SrtReport> {
+    let text = fs::read_to_string(path)?;
+    let (cues, mut issues) = parse_srt(&text);
+    let (cues, repair_issues) = repair_cues(cues);
+    issues.extend(repair_issues);
+
+    if !issues.is_empty() {
+        fs::write(path, write_srt(&cues))?;
+    }
+
+    Ok(SrtReport { cues: cues.len(), issues })
+}
+
+/// Detects the charset of a subtitle file and rewrites it as UTF-8 (without BOM).
+///
+/// Returns the name of the detected charset, e.g. "UTF-8", "windows-1252" or "UTF-16LE".
+pub fn normalize_to_utf8(path: &Path) -> io::Result<&'static str> {
+    let bytes = fs::read(path)?;
+    let (text, charset) = decode_subtitle(&bytes);
+    fs::write(path, text.as_bytes())?;
+    Ok(charset)
+}
+
+/// Decodes raw subtitle bytes into a UTF-8 string, returning the detected charset name.
+pub fn decode_subtitle(bytes: &[u8]) -> (String, &'static str) {
+    let encoding = detect_encoding(bytes);
+    let (text, _, _) = encoding.decode(bytes);
+    (text.into_owned(), encoding.name())
+}
+
+fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    // Step 1: A BOM is authoritative
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+
+    // Step 2: Valid UTF-8 needs no guessing
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+
+    // Step 3: BOM-less UTF-16 shows up as NUL bytes in every other position
+    if let Some(encoding) = sniff_utf16(bytes) {
+        return encoding;
+    }
+
+    // Step 4: Fall back to statistical detection for legacy single/multi-byte charsets
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, true)
+}
+
+fn sniff_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(4096)];
+    if sample.len() < 4 {
+        return None;
+    }
+
+    let pairs = sample.len() / 2;
+    let even_nuls = sample.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd_nuls = sample.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+
+    if odd_nuls * 10 > pairs * 4 && even_nuls * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even_nuls * 10 > pairs * 4 && odd_nuls * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_windows_1252() {
+        let bytes = b"1\r\n00:00:01,000 --> 00:00:02,000\r\nCaf\xe9 cr\xe8me br\xfbl\xe9e, d\xe9j\xe0 vu\r\n";
+        let (text, charset) = decode_subtitle(bytes);
+        assert_eq!(charset, "windows-1252");
+        assert!(text.contains("Café crème brûlée, déjà vu"));
+    }
+
+    #[test]
+    fn test_decode_utf16_with_and_without_bom() {
+        let source = "1\n00:00:01,000 --> 00:00:02,000\nHëllo\n";
+        let mut with_bom = vec![0xFF, 0xFE];
+        let mut without_bom = Vec::new();
+        for unit in source.encode_utf16() {
+            with_bom.extend_from_slice(&unit.to_le_bytes());
+            without_bom.extend_from_slice(&unit.to_le_bytes());
+        }
+
+        assert_eq!(decode_subtitle(&with_bom), (source.to_string(), "UTF-16LE"));
+        assert_eq!(decode_subtitle(&without_bom), (source.to_string(), "UTF-16LE"));
+    }
+
+    #[test]
+    fn test_parse_srt_flags_broken_and_empty_cues() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nFirst\n\n\
+                   2\n00:00:03,000 --> banana\nBroken\n\n\
+                   3\n00:00:05,000 --> 00:00:06,000\n\n\
+                   5\n00:00:07.5 --> 00:08,250\nLenient\n";
+        let (cues, issues) = parse_srt(srt);
+
+        assert_eq!(cues.len(), 2);
+        assert_eq!((cues[1].start_ms, cues[1].end_ms), (7_500, 8_250));
+        assert!(matches!(issues[0], SrtIssue::BrokenTimestamp { line: 5, .. }));
+        assert!(matches!(issues[1], SrtIssue::EmptyCue { line: 9 }));
+        assert!(matches!(
+            issues[2],
+            SrtIssue::OutOfOrderNumbering { expected: 4, found: Some(5), .. }
+        ));
+
+        let (cues, issues) = parse_srt("1\n99999999999999999:00:00,000 --> 99999999999999999:00:01,000\nHuge\n");
+        assert!(cues.is_empty());
+        assert!(matches!(issues[0], SrtIssue::BrokenTimestamp { line: 1, .. }));
+        assert_eq!(parse_timestamp("5124095576030431:00:00,000"), None);
+    }
+
+    #[test]
+    fn test_repair_cues_sorts_trims_and_renumbers() {
+        let srt = "1\n00:00:05,000 --> 00:00:07,000\nLate\n\n\
+                   2\n00:00:01,000 --> 00:00:06,000\nEarly\n\n\
+                   3\n00:00:09,000 --> 00:00:08,000\nInverted\n";
+        let (cues, _) = parse_srt(srt);
+        let (cues, issues) = repair_cues(cues);
+
+        assert_eq!(issues.len(), 3);
+        let timings: Vec<_> = cues.iter().map(|c| (c.index, c.start_ms, c.end_ms)).collect();
+        assert_eq!(timings, vec![(1, 1_000, 5_000), (2, 5_000, 7_000), (3, 9_000, 10_000)]);
+        assert!(write_srt(&cues).starts_with("1\n00:00:01,000 --> 00:00:05,000\nEarly\n\n"));
+    }
+
+    #[test]
+    fn test_shift_and_rescale_cues() {
+        let (cues, _) = parse_srt("1\n00:00:01,000 --> 00:00:02,000\nA\n\n2\n00:00:25,000 --> 00:00:26,000\nB\n");
+
+        let shifted = shift_cues(cues.clone(), -1_500);
+        assert_eq!(shifted.len(), 2);
+        assert_eq!((shifted[0].start_ms, shifted[0].end_ms), (0, 500));
+        assert!(shift_cues(cues.clone(), i64::MIN).is_empty());
+        let far = Cue { start_ms: u64::MAX - 1, end_ms: u64::MAX, ..cues[0].clone() };
+        assert_eq!(shift_cues(vec![far], i64::MAX)[0].end_ms, i64::MAX as u64);
+
+        let rescaled = rescale_cues(cues, 25.0, 23.976);
+        assert_eq!(rescaled[1].start_ms, 26_068);
+    }
+
+    #[test]
+    fn test_estimate_offset_aligns_cues_with_speech() {
+        let (cues, _) = parse_srt(
+            "1\n00:00:01,000 --> 00:00:03,000\nA\n\n\
+             2\n00:00:10,000 --> 00:00:12,000\nB\n\n\
+             3\n00:00:20,000 --> 00:00:21,500\nC\n",
+        );
+        let stderr = "  Duration: 00:00:30.00, start: 0.000000, bitrate: 128 kb/s\n\
+                      [silencedetect @ 0x1] silence_start: 0\n\
+                      [silencedetect @ 0x1] silence_end: 3.5 | silence_duration: 3.5\n\
+                      [silencedetect @ 0x1] silence_start: 5.5\n\
+                      [silencedetect @ 0x1] silence_end: 12.5 | silence_duration: 7\n\
+                      [silencedetect @ 0x1] silence_start: 14.5\n\
+                      [silencedetect @ 0x1] silence_end: 22.5 | silence_duration: 8\n\
+                      [silencedetect @ 0x1] silence_start: 24\n";
+        let speech = speech_from_silencedetect(stderr);
+        assert_eq!(speech, vec![(3_500, 5_500), (12_500, 14_500), (22_500, 24_000)]);
+
+        assert_eq!(estimate_offset(&cues, &speech, 5_000, 100), Some(2_500));
+        assert_eq!(estimate_offset(&cues, &[], 5_000, 100), None);
+    }
+
+    #[test]
+    fn test_normalize_strips_utf8_bom() {
+        let path = std::env::temp_dir().join("test_normalize_utf8_bom.srt");
+        fs::write(&path, b"\xEF\xBB\xBF1\n00:00:01,000 --> 00:00:02,000\nHi\n").unwrap();
+
+        let charset = normalize_to_utf8(&path).unwrap();
+        assert_eq!(charset, "UTF-8");
+        assert_eq!(fs::read(&path).unwrap()[0], b'1');
+
+        let _ = fs::remove_file(&path);
+    }
+}
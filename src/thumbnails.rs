@@ -0,0 +1,208 @@
+use crate::profile::Profile;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Poster candidates, spread over the middle of the video (intros and credits
+/// rarely make good posters).
+const POSTER_CANDIDATES: usize = 8;
+const POSTER_RANGE: (f64, f64) = (0.1, 0.8);
+/// Frames the `thumbnail` filter compares at each candidate to pick the most typical one.
+const THUMBNAIL_BATCH: u32 = 50;
+/// Average luma (0-255) outside this range is a black or washed-out frame.
+const BRIGHTNESS_RANGE: (f64, f64) = (40.0, 220.0);
+const SHEET_TILE_WIDTH: u32 = 320;
+
+/// Where posters and contact sheets are written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ThumbnailTarget {
+    Off,
+    /// Next to the output.
+    Beside,
+    /// In this folder; relative folders are resolved against the output's folder.
+    Dir(PathBuf),
+}
+
+/// Parses `off`, `beside` or a folder.
+pub fn parse_target(value: &str) -> Result<ThumbnailTarget, String> {
+    match value {
+        "" => Err("missing thumbnails target".into()),
+        "off" => Ok(ThumbnailTarget::Off),
+        "beside" => Ok(ThumbnailTarget::Beside),
+        dir => Ok(ThumbnailTarget::Dir(PathBuf::from(dir))),
+    }
+}
+
+/// Parses a contact sheet grid such as `4x5` (columns × rows).
+pub fn parse_grid(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid grid {:?}, expected e.g. 4x4", value);
+    let (columns, rows) = value.to_lowercase().split_once('x').map(|(c, r)| (c.trim().parse(), r.trim().parse())).ok_or_else(invalid)?;
+    match (columns, rows) {
+        (Ok(c), Ok(r)) if c > 0 && r > 0 => Ok((c, r)),
+        _ => Err(invalid()),
+    }
+}
+
+/// `(poster, contact sheet)` paths for `output`.
+pub fn thumbnail_paths(output: &Path, target: &ThumbnailTarget) -> Option<(PathBuf, PathBuf)> {
+    let folder = output.parent().unwrap_or(Path::new("."));
+    let dir = match target {
+        ThumbnailTarget::Off => return None,
+        ThumbnailTarget::Beside => folder.to_path_buf(),
+        ThumbnailTarget::Dir(dir) => folder.join(dir),
+    };
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    Some((dir.join(format!("{}.poster.jpg", stem)), dir.join(format!("{}.sheet.jpg", stem))))
+}
+
+/// Writes the poster and contact sheet for a published output, if the profile
+/// asks for them. Failures are reported but never fail the job.
+pub fn generate(profile: &Profile, output: &Path, duration: Option<f64>) {
+    let Some((poster, sheet)) = thumbnail_paths(output, &profile.thumbnails) else {
+        return;
+    };
+    let Some(duration) = duration.filter(|d| *d > 0.0) else {
+        println!("⚠️ No duration for {}, skipping thumbnails", output.display());
+        return;
+    };
+    if let Some(dir) = poster.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            println!("⚠️ Failed to create thumbnail folder {}: {}", dir.display(), e);
+            return;
+        }
+    }
+
+    match write_poster(output, duration, &poster) {
+        Ok(at) => println!("🖼️ Poster from {:.1}s: {}", at, poster.display()),
+        Err(e) => println!("⚠️ Failed to create poster for {}: {}", output.display(), e),
+    }
+    match write_contact_sheet(output, duration, profile.contact_sheet, &sheet) {
+        Ok(()) => println!("🗂️ Contact sheet: {}", sheet.display()),
+        Err(e) => println!("⚠️ Failed to create contact sheet for {}: {}", output.display(), e),
+    }
+}
+
+/// Brightness of a frame, from ffmpeg's signalstats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    /// Average luma.
+    pub average: f64,
+    /// Luma spread between the 10th and 90th percentile; flat frames score low.
+    pub contrast: f64,
+}
+
+/// Picks the candidate with the most contrast among those that are neither
+/// near-black nor washed out. Returns its time.
+pub fn pick_poster(candidates: &[(f64, FrameStats)]) -> Option<f64> {
+    candidates
+        .iter()
+        .filter(|(_, s)| s.average >= BRIGHTNESS_RANGE.0 && s.average <= BRIGHTNESS_RANGE.1)
+        .max_by(|(_, a), (_, b)| a.contrast.partial_cmp(&b.contrast).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(time, _)| *time)
+}
+
+fn write_poster(output: &Path, duration: f64, poster: &Path) -> Result<f64, String> {
+    let (from, to) = POSTER_RANGE;
+    let times = (0..POSTER_CANDIDATES).map(|i| duration * (from + (to - from) * i as f64 / (POSTER_CANDIDATES - 1) as f64));
+    let candidates: Vec<(f64, FrameStats)> =
+        times.filter_map(|t| frame_stats(output, t).map(|stats| (t, stats))).collect();
+    let at = pick_poster(&candidates).ok_or("every candidate frame was too dark or too bright")?;
+
+    let status = Command::new("ffmpeg")
+        .args(["-v", "error", "-y", "-ss", &format!("{:.3}", at), "-i"])
+        .arg(output)
+        .args(["-vf", &format!("thumbnail={}", THUMBNAIL_BATCH), "-frames:v", "1", "-q:v", "2"])
+        .arg(poster)
+        .status()
+        .map_err(|e| e.to_string())?;
+    if !status.success() {
+        return Err(format!("ffmpeg exited with {}", status));
+    }
+    Ok(at)
+}
+
+/// Stats of the most typical frame among the few after `time`.
+fn frame_stats(output: &Path, time: f64) -> Option<FrameStats> {
+    let result = Command::new("ffmpeg")
+        .args(["-v", "error", "-ss", &format!("{:.3}", time), "-i"])
+        .arg(output)
+        .args([
+            "-vf", &format!("thumbnail={},signalstats,metadata=print:file=-", THUMBNAIL_BATCH),
+            "-frames:v", "1", "-f", "null", "-",
+        ])
+        .output()
+        .ok()?;
+    parse_signalstats(&String::from_utf8_lossy(&result.stdout))
+}
+
+/// Reads `lavfi.signalstats.*` lines as printed by the metadata filter.
+pub fn parse_signalstats(text: &str) -> Option<FrameStats> {
+    let value = |key: &str| {
+        text.lines()
+            .filter_map(|l| l.trim().strip_prefix("lavfi.signalstats.")?.split_once('='))
+            .find(|(k, _)| *k == key)
+            .and_then(|(_, v)| v.trim().parse::<f64>().ok())
+    };
+    Some(FrameStats { average: value("YAVG")?, contrast: value("YHIGH")? - value("YLOW")? })
+}
+
+/// Tiles `columns × rows` evenly spaced frames, each stamped with its time.
+fn write_contact_sheet(output: &Path, duration: f64, (columns, rows): (u32, u32), sheet: &Path) -> Result<(), String> {
+    let rate = f64::from(columns * rows) / duration;
+    let filter = format!(
+        "fps={:.6},scale={}:-2,drawtext=text='%{{pts\\:hms}}':x=6:y=h-th-6:fontsize=18:fontcolor=white:box=1:boxcolor=black@0.6,tile={}x{}",
+        rate, SHEET_TILE_WIDTH, columns, rows
+    );
+    // Keyframes are plenty for a sheet and spare decoding the whole file
+    let result = Command::new("ffmpeg")
+        .args(["-v", "error", "-y", "-skip_frame", "nokey", "-i"])
+        .arg(output)
+        .args(["-vf", &filter, "-frames:v", "1", "-q:v", "3"])
+        .arg(sheet)
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !result.status.success() {
+        return Err(String::from_utf8_lossy(&result.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_settings_and_paths() {
+        assert_eq!(parse_target("beside"), Ok(ThumbnailTarget::Beside));
+        assert_eq!(parse_target(".thumbs"), Ok(ThumbnailTarget::Dir(".thumbs".into())));
+        assert_eq!(parse_grid("5X3"), Ok((5, 3)));
+        assert!(parse_grid("0x3").is_err());
+        assert!(parse_grid("many").is_err());
+
+        let output = Path::new("/media/show/e01.mp4");
+        assert_eq!(thumbnail_paths(output, &ThumbnailTarget::Off), None);
+        assert_eq!(
+            thumbnail_paths(output, &ThumbnailTarget::Dir(".thumbs".into())),
+            Some((PathBuf::from("/media/show/.thumbs/e01.poster.jpg"), PathBuf::from("/media/show/.thumbs/e01.sheet.jpg")))
+        );
+        assert_eq!(
+            thumbnail_paths(output, &ThumbnailTarget::Dir("/srv/thumbs".into())).unwrap().0,
+            PathBuf::from("/srv/thumbs/e01.poster.jpg")
+        );
+    }
+
+    #[test]
+    fn test_poster_skips_dark_and_flat_frames() {
+        let text = "frame:0    pts:1001   pts_time:1.001\nlavfi.signalstats.YMIN=16\nlavfi.signalstats.YLOW=30\n\
+                    lavfi.signalstats.YAVG=96.5\nlavfi.signalstats.YHIGH=170\n";
+        let stats = parse_signalstats(text).unwrap();
+        assert_eq!(stats, FrameStats { average: 96.5, contrast: 140.0 });
+        assert_eq!(parse_signalstats("frame:0"), None);
+
+        let black = FrameStats { average: 17.0, contrast: 200.0 };
+        let flat = FrameStats { average: 120.0, contrast: 10.0 };
+        let busy = FrameStats { average: 90.0, contrast: 120.0 };
+        assert_eq!(pick_poster(&[(10.0, black), (20.0, flat), (30.0, busy)]), Some(30.0));
+        assert_eq!(pick_poster(&[(10.0, black)]), None);
+    }
+}
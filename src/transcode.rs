@@ -0,0 +1,717 @@
+use crate::chunked::{self, ChunkSettings, Segment};
+use crate::config::load_config;
+use crate::disposal::{self, SourceAction};
+use crate::gpu::{detect_devices, detect_gpu_from_devices};
+use crate::hooks::{configured_hooks, HookEvent, Hooks, JOB_FAILED, JOB_STARTED, JOB_SUCCEEDED};
+use crate::joblog::JobLog;
+use crate::metadata;
+use crate::plan::FfmpegPlan;
+use crate::probe::probe;
+use crate::profile::{active_profile, Profile};
+use crate::progress;
+use crate::publish::{partial_path, publish};
+use crate::resources::{configured_limits, ProcessLimits};
+use crate::shutdown::{self, ActiveJob};
+use crate::verify::{self, quarantine, verify_output};
+use crate::watchdog::{kill_process_group, Timeouts, Watchdog};
+use crate::workspace::{preflight, JobWorkspace};
+use crate::sidecar::load_file_options;
+use crate::slots::{self, Device, SlotLimits};
+use crate::subtitles::{normalize_to_utf8, repair_srt_file, retime_srt_file};
+use crate::thumbnails;
+use chrono::Local;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const TEMP_DIR: &str = "/tmp/video_convert_work";
+
+/// How often a running ffmpeg is checked for timeouts and stalls.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Everything a single transcode needs to know besides the input path.
+#[derive(Debug, Clone)]
+pub struct JobOptions {
+    /// "nvenc", "vaapi" or "cpu", as returned by `gpu::detect_gpu_from_devices`.
+    pub gpu_type: &'static str,
+    /// The devices of `gpu_type` jobs are spread over.
+    pub gpus: Vec<Device>,
+    pub slot_limits: SlotLimits,
+    pub profile: Profile,
+    /// External subtitle to mux in, if any.
+    pub subtitle: Option<PathBuf>,
+    pub log_dir: PathBuf,
+    pub temp_dir: PathBuf,
+    /// Where outputs that fail verification are moved.
+    pub quarantine_dir: PathBuf,
+    pub hooks: Hooks,
+    pub timeouts: Timeouts,
+    /// Priority, CPU affinity and thread count for ffmpeg.
+    pub limits: ProcessLimits,
+    pub chunking: ChunkSettings,
+}
+
+impl JobOptions {
+    /// Options for this machine and environment: detected GPU, the active profile,
+    /// the configured log dir and the default temp dir. No subtitle.
+    pub fn detect() -> Self {
+        let cfg = load_config();
+        let gpu_type = detect_gpu_from_devices();
+        let slot_limits = SlotLimits::parse(&cfg.slot_limits).unwrap_or_else(|e| {
+            println!("⚠️ Invalid SLOT_LIMITS ({}), using defaults", e);
+            SlotLimits::default()
+        });
+        JobOptions {
+            gpu_type,
+            gpus: detect_devices(gpu_type),
+            slot_limits,
+            profile: active_profile(),
+            subtitle: None,
+            log_dir: PathBuf::from(cfg.log_dir),
+            temp_dir: PathBuf::from(TEMP_DIR),
+            quarantine_dir: PathBuf::from(cfg.quarantine_dir),
+            hooks: configured_hooks(cfg.hook_dir.as_deref(), cfg.hook_timeout_secs, cfg.hook_veto_recheck_secs),
+            timeouts: Timeouts {
+                factor: cfg.timeout_factor,
+                min: Duration::from_secs(cfg.min_timeout_secs),
+                stall: Duration::from_secs(cfg.stall_secs),
+            },
+            limits: configured_limits(
+                cfg.ffmpeg_nice,
+                cfg.ffmpeg_ionice.as_deref(),
+                cfg.ffmpeg_cpus.as_deref(),
+                cfg.ffmpeg_threads,
+            ),
+            chunking: ChunkSettings {
+                segment_secs: cfg.chunk_secs,
+                workers: cfg.chunk_workers,
+                scene_aware: cfg.chunk_scenes,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobAction {
+    /// Video was re-encoded.
+    Encoded,
+    /// The output already existed; nothing was done.
+    Skipped,
+}
+
+#[derive(Debug, Clone)]
+pub struct JobOutcome {
+    pub action: JobAction,
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub input_size: u64,
+    pub output_size: u64,
+    /// Media duration of the source in seconds, when it could be probed.
+    pub media_duration: Option<f64>,
+    /// Wall-clock time spent on the job.
+    pub elapsed: Duration,
+}
+
+#[derive(Debug)]
+pub enum TranscodeError {
+    /// A filesystem step failed; `stage` says which.
+    Io { stage: &'static str, error: io::Error },
+    /// ffmpeg could not be started or waited on.
+    Spawn(io::Error),
+    /// Not enough disk space right now; the job should be tried again later.
+    Deferred(String),
+    /// The job-started hook refused the job.
+    Vetoed(String),
+    /// The daemon is shutting down; the job was stopped or never started.
+    Interrupted,
+    /// ffmpeg ran and exited unsuccessfully. Details are in the job log.
+    Ffmpeg { status: ExitStatus, log: PathBuf },
+    /// ffmpeg ran too long or stopped making progress and was killed. Worth retrying.
+    TimedOut { reason: String, log: PathBuf },
+    /// ffmpeg succeeded but the output doesn't match the plan. The output was
+    /// moved to `quarantined` when that was possible.
+    Verification { problems: Vec<String>, quarantined: Option<PathBuf> },
+}
+
+impl fmt::Display for TranscodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranscodeError::Io { stage, error } => write!(f, "{}: {}", stage, error),
+            TranscodeError::Spawn(e) => write!(f, "failed to run ffmpeg: {}", e),
+            TranscodeError::Deferred(reason) => write!(f, "deferred: {}", reason),
+            TranscodeError::Vetoed(reason) => write!(f, "vetoed: {}", reason),
+            TranscodeError::Interrupted => write!(f, "interrupted by shutdown"),
+            TranscodeError::Ffmpeg { status, log } => {
+                write!(f, "ffmpeg exited with {} (see {})", status, log.display())
+            }
+            TranscodeError::TimedOut { reason, log } => {
+                write!(f, "ffmpeg timed out: {} (see {})", reason, log.display())
+            }
+            TranscodeError::Verification { problems, .. } => {
+                write!(f, "output failed verification: {}", problems.join("; "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for TranscodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TranscodeError::Io { error, .. } | TranscodeError::Spawn(error) => Some(error),
+            TranscodeError::Deferred(_)
+            | TranscodeError::Vetoed(_)
+            | TranscodeError::Interrupted
+            | TranscodeError::Ffmpeg { .. }
+            | TranscodeError::TimedOut { .. }
+            | TranscodeError::Verification { .. } => None,
+        }
+    }
+}
+
+fn io_error(stage: &'static str) -> impl FnOnce(io::Error) -> TranscodeError {
+    move |error| TranscodeError::Io { stage, error }
+}
+
+/// Converts one file to MP4 beside the input (`<stem>.mp4`).
+///
+/// The output is encoded and verified in `options.temp_dir` and only then moved
+/// into place, so an existing `<stem>.mp4` is always a finished one.
+pub fn transcode_file(input_file: &Path, options: &JobOptions) -> Result<JobOutcome, TranscodeError> {
+    let start_time = Instant::now();
+    let base = input_file
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let input_size = fs::metadata(input_file).map_err(io_error("read source"))?.len();
+
+    let output_file = input_file.with_extension("mp4");
+    if output_file.exists() {
+        println!("🟡 Already exists: {:?}", output_file);
+        return Ok(JobOutcome {
+            action: JobAction::Skipped,
+            input: input_file.to_path_buf(),
+            output_size: fs::metadata(&output_file).map(|m| m.len()).unwrap_or(0),
+            output: output_file,
+            input_size,
+            media_duration: None,
+            elapsed: start_time.elapsed(),
+        });
+    }
+
+    let hook_event = |event| HookEvent {
+        event,
+        input: input_file.to_path_buf(),
+        profile: options.profile.name.clone(),
+        input_size: Some(input_size),
+        ..HookEvent::default()
+    };
+    if shutdown::requested() {
+        return Err(TranscodeError::Interrupted);
+    }
+
+    let active = shutdown::register_job(input_file);
+    let job_log = JobLog::new(&options.log_dir, &base);
+    job_log.start(input_file);
+    let result = run_job(input_file, input_size, &output_file, options, &job_log, &active, start_time);
+    // Whatever went wrong while shutting down is most likely the shutdown itself
+    let result = match result {
+        Err(_) if shutdown::requested() => Err(TranscodeError::Interrupted),
+        result => result,
+    };
+    match &result {
+        Ok(outcome) => {
+            job_log.finish("converted", outcome.elapsed);
+            options.hooks.notify(&HookEvent {
+                output: Some(outcome.output.clone()),
+                duration: outcome.media_duration,
+                output_size: Some(outcome.output_size),
+                ..hook_event(JOB_SUCCEEDED)
+            });
+        }
+        Err(e) => {
+            job_log.finish(&format!("failed: {}", e), start_time.elapsed());
+            // Deferred, vetoed and interrupted jobs will be tried again, so they haven't failed yet
+            if !matches!(e, TranscodeError::Deferred(_) | TranscodeError::Vetoed(_) | TranscodeError::Interrupted) {
+                options.hooks.notify(&HookEvent {
+                    output: Some(output_file.clone()),
+                    error: Some(e.to_string()),
+                    ..hook_event(JOB_FAILED)
+                });
+            }
+        }
+    }
+    result
+}
+
+fn run_job(
+    input_file: &Path,
+    input_size: u64,
+    output_file: &Path,
+    options: &JobOptions,
+    job_log: &JobLog,
+    active: &ActiveJob,
+    start_time: Instant,
+) -> Result<JobOutcome, TranscodeError> {
+    let base = &*input_file.file_stem().unwrap_or_default().to_string_lossy();
+    let profile = &options.profile;
+    fs::create_dir_all(&options.temp_dir).map_err(io_error("create temp dir"))?;
+
+    let dest_dir = output_file
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    preflight(input_size, &options.temp_dir, dest_dir).map_err(TranscodeError::Deferred)?;
+
+    // Removed again when this function returns, however it returns
+    let workspace = JobWorkspace::create(&options.temp_dir, input_file).map_err(io_error("create job workspace"))?;
+    let temp_dir = workspace.path();
+    active.cleanup_on_exit(temp_dir);
+    active.cleanup_on_exit(&partial_path(output_file));
+
+    let temp_input = temp_dir.join(input_file.file_name().unwrap_or_default());
+    fs::copy(input_file, &temp_input).map_err(io_error("copy to temp"))?;
+
+    println!(
+        "\n[{}] 🎬 Converting: {:?}",
+        Local::now().format("%Y-%m-%d %H:%M:%S"),
+        input_file
+    );
+
+    let source_info = match probe(&temp_input) {
+        Ok(info) => Some(info),
+        Err(e) => {
+            println!("⚠️ Failed to probe source, metadata policy is best-effort: {}", e);
+            None
+        }
+    };
+
+    let temp_srt = match options.subtitle.as_deref() {
+        Some(srt_path) => prepare_subtitle(input_file, srt_path, &temp_input, temp_dir, job_log)?,
+        None => {
+            println!("🕳️ No subtitle found for: {:?}", input_file);
+            None
+        }
+    };
+
+    let metadata_inputs = metadata::prepare_inputs(&profile.metadata, source_info.as_ref(), input_file, temp_dir)
+        .map_err(io_error("prepare metadata inputs"))?;
+
+    // Encode into the temp dir; the real output only appears once it has been verified
+    let staged_output = temp_dir.join(output_file.file_name().unwrap_or_default());
+
+    // Wait for room on an encoder
+    let gpus = match (options.gpu_type, options.gpus.is_empty()) {
+        ("cpu", _) => Vec::new(),
+        // Detected, but its device nodes weren't listed; let ffmpeg pick
+        (backend, true) => vec![Device { backend, id: String::new() }],
+        (_, false) => options.gpus.clone(),
+    };
+    let slot = slots::acquire(&options.slot_limits, &gpus, profile.cpu_overflow);
+    let gpu_type = slot.device.backend;
+    let device = Some(slot.device.id.as_str()).filter(|id| !id.is_empty());
+    if gpu_type != options.gpu_type {
+        println!("🧮 All {} slots busy, encoding {} on the CPU", options.gpu_type, base);
+    }
+    if let Some(device) = device {
+        job_log.line(&format!("encoder: {} on {}", gpu_type, device));
+    }
+
+    // Only now is ffmpeg about to run: the job has its disk space and its encoder
+    let started = HookEvent {
+        event: JOB_STARTED,
+        input: input_file.to_path_buf(),
+        profile: profile.name.clone(),
+        input_size: Some(input_size),
+        ..HookEvent::default()
+    };
+    options.hooks.run(&started).map_err(TranscodeError::Vetoed)?;
+
+    // Long sources can be split at keyframes and encoded in pieces side by side.
+    // The pieces share the job's slot; CHUNK_WORKERS says how many run at once.
+    let duration = source_info.as_ref().and_then(|info| info.duration);
+    let segments = match duration {
+        Some(duration) if options.chunking.applies_to(duration) => {
+            chunked::plan_segments(&temp_input, duration, &options.chunking)
+        }
+        _ => Vec::new(),
+    };
+    let concat_list = if segments.is_empty() {
+        None
+    } else {
+        println!("🧩 Encoding {} in {} segments", base, segments.len());
+        job_log.line(&format!("chunked: {} segments", segments.len()));
+        let segment_plan = |segment: &Segment, output: &Path| {
+            FfmpegPlan::builder(&temp_input, output, profile)
+                .gpu(gpu_type)
+                .device(device)
+                .threads(options.limits.threads)
+                .segment(Some((segment.start, segment.end)))
+                .build()
+        };
+        let segment_dir = temp_dir.join("segments");
+        fs::create_dir_all(&segment_dir).map_err(io_error("create segment dir"))?;
+        Some(encode_segments(&segments, &segment_dir, segment_plan, base, options, job_log, active)?)
+    };
+
+    let plan = FfmpegPlan::builder(&temp_input, &staged_output, profile)
+        .gpu(gpu_type)
+        .device(device)
+        .threads(options.limits.threads)
+        .video_from(concat_list.as_deref())
+        .media(source_info.as_ref())
+        .subtitle(temp_srt.as_deref())
+        .metadata_inputs(&metadata_inputs)
+        .build();
+    if !matches!(options.gpu_type, "nvenc" | "vaapi") {
+        println!("⚠️ GPU not available or unsupported, falling back to CPU encoding.");
+    }
+
+    run_ffmpeg(&plan, base, duration, options, job_log, active)?;
+    drop(slot);
+
+    // Joined segments must also still line up with the audio, which was encoded in one go
+    let verified = verify_output(&staged_output, &plan.expected).and_then(|info| {
+        let problems = match (&concat_list, &source_info) {
+            (Some(_), Some(source_info)) => verify::av_sync(source_info, &info),
+            _ => Vec::new(),
+        };
+        if problems.is_empty() {
+            Ok(info)
+        } else {
+            Err(problems)
+        }
+    });
+    let output_info = match verified {
+        Ok(info) => info,
+        Err(problems) => {
+            for problem in &problems {
+                println!("🚫 Verification failed for {}: {}", base, problem);
+                job_log.line(&format!("verification: {}", problem));
+            }
+            let quarantined = match quarantine(&staged_output, &options.quarantine_dir) {
+                Ok(path) => {
+                    println!("🧪 Quarantined output: {}", path.display());
+                    Some(path)
+                }
+                Err(e) => {
+                    println!("⚠️ Failed to quarantine output: {}", e);
+                    None
+                }
+            };
+            return Err(TranscodeError::Verification { problems, quarantined });
+        }
+    };
+
+    publish(&staged_output, output_file).map_err(io_error("publish output"))?;
+    job_log.line(&format!("published: {}", output_file.display()));
+
+    let elapsed = start_time.elapsed();
+    println!("🏁 Done {} in {:.2?}", base, elapsed);
+
+    if let Some(source_info) = &source_info {
+        let problems = metadata::verify(&profile.metadata, source_info, &output_info, &metadata_inputs, base);
+        for problem in &problems {
+            println!("⚠️ Metadata policy not honoured: {}", problem);
+            job_log.line(&format!("metadata: {}", problem));
+        }
+    }
+
+    thumbnails::generate(profile, output_file, output_info.duration.or(duration));
+
+    // Only reached once the output has passed verification and is published
+    if profile.source_action != SourceAction::Keep {
+        // The subtitle is muxed into the output, so it follows the video, except that
+        // only the video's timestamps should carry over on replace
+        let subtitle_action = match &profile.source_action {
+            SourceAction::Replace => SourceAction::Delete,
+            action => action.clone(),
+        };
+        let sources = std::iter::once((input_file, &profile.source_action))
+            .chain(options.subtitle.as_deref().map(|srt| (srt, &subtitle_action)));
+        for (source, action) in sources {
+            match disposal::apply(action, source, output_file) {
+                Ok(Some(moved)) => {
+                    println!("📦 Moved source {} to {}", source.display(), moved.display());
+                    job_log.line(&format!("source moved: {}", moved.display()));
+                }
+                Ok(None) => {
+                    println!("🧹 Removed source {}", source.display());
+                    job_log.line(&format!("source removed: {}", source.display()));
+                }
+                Err(e) => {
+                    println!("⚠️ Failed to handle source {}: {}", source.display(), e);
+                    job_log.line(&format!("source action failed: {}", e));
+                }
+            }
+        }
+    }
+
+    Ok(JobOutcome {
+        action: JobAction::Encoded,
+        input: input_file.to_path_buf(),
+        output: output_file.to_path_buf(),
+        input_size,
+        output_size: fs::metadata(output_file).map(|m| m.len()).unwrap_or(0),
+        media_duration: duration,
+        elapsed,
+    })
+}
+
+/// Copies the subtitle to the temp dir, normalizes its charset, repairs it and
+/// applies sidecar timing fixes. Returns `None` when it turns out to be unusable.
+pub(crate) fn prepare_subtitle(
+    input_file: &Path,
+    srt_path: &Path,
+    temp_input: &Path,
+    temp_dir: &Path,
+    job_log: &JobLog,
+) -> Result<Option<PathBuf>, TranscodeError> {
+    let temp_srt = temp_dir.join(srt_path.file_name().unwrap_or_default());
+    fs::copy(srt_path, &temp_srt).map_err(io_error("copy subtitle to temp"))?;
+    println!("💬 Subtitle copied to temp: {:?}", temp_srt);
+
+    match normalize_to_utf8(&temp_srt) {
+        Ok(charset) => {
+            println!("🔤 Subtitle charset: {} (normalized to UTF-8)", charset);
+            job_log.line(&format!("subtitle charset: {}", charset));
+        }
+        Err(e) => println!("⚠️ Failed to normalize subtitle charset: {}", e),
+    }
+
+    match repair_srt_file(&temp_srt) {
+        Ok(report) if report.cues == 0 => {
+            println!("⚠️ Subtitle has no usable cues, converting without it");
+            job_log.line("subtitle dropped: no usable cues");
+            return Ok(None);
+        }
+        Ok(report) => {
+            if !report.issues.is_empty() {
+                println!("🩹 Repaired {} subtitle issue(s)", report.issues.len());
+            }
+            for issue in &report.issues {
+                job_log.line(&format!("subtitle: {}", issue));
+            }
+        }
+        Err(e) => {
+            println!("⚠️ Failed to parse subtitle, converting without it: {}", e);
+            job_log.line(&format!("subtitle dropped: {}", e));
+            return Ok(None);
+        }
+    }
+
+    let file_options = load_file_options(input_file);
+    if file_options.has_subtitle_timing() {
+        match retime_srt_file(&temp_srt, file_options.subtitle_fps, file_options.subtitle_offset, temp_input) {
+            Ok(offset_ms) => {
+                println!("⏱️ Subtitle retimed (offset {}ms)", offset_ms);
+                job_log.line(&format!(
+                    "subtitle retimed: fps {:?}, offset {}ms",
+                    file_options.subtitle_fps, offset_ms
+                ));
+            }
+            Err(e) => println!("⚠️ Failed to retime subtitle: {}", e),
+        }
+    }
+
+    Ok(Some(temp_srt))
+}
+
+/// Encodes `segments` into `dir`, up to `options.chunking.workers` at a time, and
+/// returns a concat list of the results in order. Stops at the first failure.
+fn encode_segments(
+    segments: &[Segment],
+    dir: &Path,
+    plan_for: impl Fn(&Segment, &Path) -> FfmpegPlan + Sync,
+    base: &str,
+    options: &JobOptions,
+    job_log: &JobLog,
+    active: &ActiveJob,
+) -> Result<PathBuf, TranscodeError> {
+    let outputs: Vec<PathBuf> = segments.iter().map(|s| dir.join(format!("{:04}.mkv", s.index))).collect();
+    chunked::run_parallel(segments, options.chunking.workers, |segment| {
+        let output = &outputs[segment.index];
+        let plan = plan_for(segment, output);
+        let job = format!("{} [{}/{}]", base, segment.index + 1, segments.len());
+        run_ffmpeg(&plan, &job, Some(segment.end - segment.start), options, job_log, active).and_then(|()| {
+            verify_output(output, &plan.expected)
+                .map(|_| ())
+                .map_err(|problems| TranscodeError::Verification { problems, quarantined: None })
+        })
+    })?;
+    chunked::write_concat_list(dir, &outputs).map_err(io_error("write segment list"))
+}
+
+/// Spawns the planned ffmpeg, streams progress and stderr, and waits for it.
+pub(crate) fn run_ffmpeg(
+    plan: &FfmpegPlan,
+    job: &str,
+    duration: Option<f64>,
+    options: &JobOptions,
+    job_log: &JobLog,
+    active: &ActiveJob,
+) -> Result<(), TranscodeError> {
+    let mut command = plan.command();
+    // Own process group, so a timeout kill also reaches anything ffmpeg spawned
+    command.stdout(Stdio::piped()).stderr(Stdio::piped()).process_group(0);
+    options.limits.apply(&mut command);
+
+    // Print for debugging
+    println!("🛠️ Running ffmpeg command: {}", plan.display());
+    job_log.command(&command);
+
+    let mut child = command.spawn().map_err(TranscodeError::Spawn)?;
+    let pid = child.id();
+    println!("🚀 PID: {}", pid);
+    active.ffmpeg_started(pid);
+    // stderr goes to the job log and progress is tracked on their own threads, so
+    // neither pipe can fill up and this thread is free to watch the clock
+    let stderr_capture = child.stderr.take().map(|stderr| {
+        let job_log = job_log.clone();
+        thread::spawn(move || job_log.capture(stderr))
+    });
+    let progress_tracker = child.stdout.take().map(|stdout| {
+        let job = job.to_string();
+        thread::spawn(move || progress::track(&job, stdout, duration))
+    });
+
+    let mut watchdog = Watchdog::new(&options.timeouts, duration, Instant::now());
+    let wait_result = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Ok(status),
+            Ok(None) => {}
+            Err(e) => break Err(e),
+        }
+        if let Some(reason) = watchdog.observe(progress::current(job).as_ref(), Instant::now()) {
+            println!("⏱️ Killing ffmpeg for {}: {}", job, reason);
+            job_log.line(&format!("killed: {}", reason));
+            kill_process_group(&mut child);
+            active.ffmpeg_finished(pid);
+            join_readers(stderr_capture, progress_tracker);
+            return Err(TranscodeError::TimedOut {
+                reason,
+                log: job_log.path().to_path_buf(),
+            });
+        }
+        thread::sleep(WATCHDOG_INTERVAL);
+    };
+    active.ffmpeg_finished(pid);
+    join_readers(stderr_capture, progress_tracker);
+
+    let status = wait_result.map_err(TranscodeError::Spawn)?;
+    if !status.success() {
+        println!("💥 ffmpeg exited with error: {:?}", status);
+        println!("📄 Check log file: {}", job_log.path().display());
+        return Err(TranscodeError::Ffmpeg {
+            status,
+            log: job_log.path().to_path_buf(),
+        });
+    }
+    Ok(())
+}
+
+fn join_readers<A, B>(stderr: Option<thread::JoinHandle<A>>, stdout: Option<thread::JoinHandle<B>>) {
+    if let Some(handle) = stderr {
+        let _ = handle.join();
+    }
+    if let Some(handle) = stdout {
+        let _ = handle.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_existing_output_is_skipped() {
+        let dir = std::env::temp_dir().join("test_transcode_skip");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("done.mkv"), b"mkv").unwrap();
+        fs::write(dir.join("done.mp4"), b"mp4!").unwrap();
+
+        let options = JobOptions {
+            gpu_type: "cpu",
+            gpus: Vec::new(),
+            slot_limits: SlotLimits::default(),
+            profile: Profile::default(),
+            subtitle: None,
+            log_dir: dir.join("logs"),
+            temp_dir: dir.join("work"),
+            quarantine_dir: dir.join("quarantine"),
+            hooks: Hooks::default(),
+            timeouts: Timeouts::default(),
+            limits: ProcessLimits::default(),
+            chunking: ChunkSettings::default(),
+        };
+        let outcome = transcode_file(&dir.join("done.mkv"), &options).unwrap();
+
+        assert_eq!(outcome.action, JobAction::Skipped);
+        assert_eq!((outcome.input_size, outcome.output_size), (3, 4));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_job_started_hook_runs_once_the_job_is_set_up() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join("test_transcode_started_hook");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("hooks")).unwrap();
+        fs::write(dir.join("show.mkv"), b"mkv").unwrap();
+        // Lists the job's workspace, then vetoes
+        let hook = dir.join("hooks").join(JOB_STARTED);
+        fs::write(&hook, format!("#!/bin/sh
+ls {0}/work > {0}/seen
+exit 1
+", dir.display())).unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let options = JobOptions {
+            gpu_type: "cpu",
+            gpus: Vec::new(),
+            slot_limits: SlotLimits::default(),
+            profile: Profile::default(),
+            subtitle: None,
+            log_dir: dir.join("logs"),
+            temp_dir: dir.join("work"),
+            quarantine_dir: dir.join("quarantine"),
+            hooks: Hooks { dir: Some(dir.join("hooks")), ..Hooks::default() },
+            timeouts: Timeouts::default(),
+            limits: ProcessLimits::default(),
+            chunking: ChunkSettings::default(),
+        };
+        let err = transcode_file(&dir.join("show.mkv"), &options).unwrap_err();
+
+        assert!(matches!(err, TranscodeError::Vetoed(_)), "{}", err);
+        assert!(!fs::read_to_string(dir.join("seen")).unwrap().is_empty());
+        assert_eq!(fs::read_dir(dir.join("work")).unwrap().count(), 0);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_missing_input_is_an_io_error() {
+        let options = JobOptions {
+            gpu_type: "cpu",
+            gpus: Vec::new(),
+            slot_limits: SlotLimits::default(),
+            profile: Profile::default(),
+            subtitle: None,
+            log_dir: std::env::temp_dir(),
+            temp_dir: std::env::temp_dir(),
+            quarantine_dir: std::env::temp_dir(),
+            hooks: Hooks::default(),
+            timeouts: Timeouts::default(),
+            limits: ProcessLimits::default(),
+            chunking: ChunkSettings::default(),
+        };
+        let err = transcode_file(Path::new("/nonexistent/missing.mkv"), &options).unwrap_err();
+        assert!(matches!(err, TranscodeError::Io { stage: "read source", .. }));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::probe::{probe, MediaInfo};
+use chrono::Local;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Allowed duration drift: whichever is larger of this many seconds or `DURATION_TOLERANCE_RATIO`.
+const DURATION_TOLERANCE_SECS: f64 = 2.0;
+const DURATION_TOLERANCE_RATIO: f64 = 0.01;
+/// How far the video/audio length gap of a chunked output may move from the source's.
+const AV_SYNC_TOLERANCE_SECS: f64 = 0.25;
+
+/// What a finished output should look like, derived from the plan and the probed source.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputExpectation {
+    /// Source duration in seconds; `None` skips the duration check.
+    pub duration: Option<f64>,
+    pub video: usize,
+    /// `None` when the source couldn't be probed and the audio count is unknown.
+    pub audio: Option<usize>,
+    /// `None` when a subtitle may have been dropped on the way and either count is fine.
+    pub subtitle: Option<usize>,
+}
+
+/// Probes `output`, compares it with `expected` and test-decodes its first and last second.
+/// Returns the probed output on success, or every problem found.
+pub fn verify_output(output: &Path, expected: &OutputExpectation) -> Result<MediaInfo, Vec<String>> {
+    let info = probe(output).map_err(|e| vec![e.to_string()])?;
+
+    let mut problems = compare(expected, &info);
+    for from_end in [false, true] {
+        if let Err(e) = decode_check(output, from_end) {
+            problems.push(e);
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(info)
+    } else {
+        Err(problems)
+    }
+}
+
+/// Compares probed output information against the expectation.
+pub fn compare(expected: &OutputExpectation, actual: &MediaInfo) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if let Some(expected_duration) = expected.duration {
+        let tolerance = DURATION_TOLERANCE_SECS.max(expected_duration * DURATION_TOLERANCE_RATIO);
+        match actual.duration {
+            Some(duration) if (duration - expected_duration).abs() <= tolerance => {}
+            Some(duration) => problems.push(format!(
+                "duration {:.2}s differs from source {:.2}s by more than {:.2}s",
+                duration, expected_duration, tolerance
+            )),
+            None => problems.push("output has no duration".to_string()),
+        }
+    }
+
+    let counts = [
+        ("video", Some(expected.video)),
+        ("audio", expected.audio),
+        ("subtitle", expected.subtitle),
+    ];
+    for (kind, expected_count) in counts {
+        let found = actual.streams_of(kind).len();
+        if let Some(expected_count) = expected_count.filter(|n| *n != found) {
+            problems.push(format!("expected {} {} stream(s), found {}", expected_count, kind, found));
+        }
+    }
+
+    problems
+}
+
+/// Checks that joining separately encoded video segments hasn't pulled audio and
+/// video apart: the gap between the first video and audio stream lengths must stay
+/// what it was in the source (zero when the source doesn't say).
+pub fn av_sync(source: &MediaInfo, output: &MediaInfo) -> Vec<String> {
+    let gap = |info: &MediaInfo| {
+        let video = info.streams_of("video").first()?.duration?;
+        let audio = info.streams_of("audio").first()?.duration?;
+        Some(video - audio)
+    };
+    if output.streams_of("audio").is_empty() {
+        return Vec::new();
+    }
+    match gap(output) {
+        Some(gap_out) => {
+            let drift = gap_out - gap(source).unwrap_or(0.0);
+            if drift.abs() > AV_SYNC_TOLERANCE_SECS {
+                vec![format!("video and audio lengths drifted {:+.3}s apart", drift)]
+            } else {
+                Vec::new()
+            }
+        }
+        None => vec!["output streams have no duration to check A/V sync".to_string()],
+    }
+}
+
+/// Decodes one second at the start (or end) of `path` and fails on any decode error.
+fn decode_check(path: &Path, from_end: bool) -> Result<(), String> {
+    let mut command = Command::new("ffmpeg");
+    command.args(["-v", "error", "-xerror"]);
+    if from_end {
+        command.args(["-sseof", "-1"]);
+    }
+    let output = command
+        .arg("-i")
+        .arg(path)
+        .args(["-t", "1", "-f", "null", "-"])
+        .output()
+        .map_err(|e| format!("failed to run decode check: {}", e))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let which = if from_end { "last" } else { "first" };
+    if !output.status.success() || !stderr.trim().is_empty() {
+        return Err(format!("{} second does not decode: {}", which, stderr.trim()));
+    }
+    Ok(())
+}
+
+/// Moves a failed output into `quarantine_dir`, stamped so repeated failures don't collide.
+pub fn quarantine(output: &Path, quarantine_dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(quarantine_dir)?;
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = output.extension().unwrap_or_default().to_string_lossy();
+    let target = quarantine_dir.join(format!(
+        "{}.{}.{}",
+        stem,
+        Local::now().format("%Y%m%d-%H%M%S"),
+        extension
+    ));
+
+    // The quarantine may be on another filesystem than the watch folder
+    if fs::rename(output, &target).is_err() {
+        fs::copy(output, &target)?;
+        fs::remove_file(output)?;
+    }
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::StreamInfo;
+
+    fn media(duration: f64, kinds: &[&str]) -> MediaInfo {
+        MediaInfo {
+            duration: Some(duration),
+            streams: kinds
+                .iter()
+                .map(|k| StreamInfo { codec_type: k.to_string(), ..Default::default() })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_compare_accepts_matching_output() {
+        let expected = OutputExpectation { duration: Some(1200.0), video: 1, audio: Some(2), subtitle: Some(1) };
+        let actual = media(1208.5, &["video", "audio", "audio", "subtitle"]);
+        assert!(compare(&expected, &actual).is_empty());
+    }
+
+    #[test]
+    fn test_compare_reports_truncation_and_missing_streams() {
+        let expected = OutputExpectation { duration: Some(1200.0), video: 1, audio: Some(2), subtitle: Some(1) };
+        let actual = media(600.0, &["video", "audio"]);
+
+        let problems = compare(&expected, &actual);
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].starts_with("duration 600.00s"));
+        assert_eq!(problems[1], "expected 2 audio stream(s), found 1");
+        assert_eq!(problems[2], "expected 1 subtitle stream(s), found 0");
+    }
+
+    #[test]
+    fn test_av_sync_compares_gap_with_source() {
+        let with_lengths = |video: f64, audio: f64| {
+            let mut info = media(video, &["video", "audio"]);
+            info.streams[0].duration = Some(video);
+            info.streams[1].duration = Some(audio);
+            info
+        };
+        // The source's own 0.5s gap is fine; an extra 0.4s from joining is not
+        assert!(av_sync(&with_lengths(600.0, 599.5), &with_lengths(600.04, 599.5)).is_empty());
+        assert_eq!(av_sync(&with_lengths(600.0, 599.5), &with_lengths(600.4, 599.5)).len(), 1);
+        assert_eq!(av_sync(&media(600.0, &["video"]), &with_lengths(600.0, 600.0)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_quarantine_moves_output() {
+        let dir = std::env::temp_dir().join("test_quarantine_moves_output");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("broken.mp4");
+        fs::write(&output, b"truncated").unwrap();
+
+        let target = quarantine(&output, &dir.join("quarantine")).unwrap();
+        assert!(!output.exists());
+        assert_eq!(fs::read(&target).unwrap(), b"truncated");
+        assert!(target.file_name().unwrap().to_string_lossy().starts_with("broken."));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::progress::Progress;
+use std::process::Child;
+use std::time::{Duration, Instant};
+
+/// Limits on how long one ffmpeg run may take.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeouts {
+    /// Allowed wall-clock time per second of source media.
+    pub factor: f64,
+    /// Lower bound for the wall-clock limit, so short clips aren't cut off by startup costs.
+    pub min: Duration,
+    /// How long progress may stand still before the job counts as stalled.
+    pub stall: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            factor: 4.0,
+            min: Duration::from_secs(30 * 60),
+            stall: Duration::from_secs(180),
+        }
+    }
+}
+
+impl Timeouts {
+    /// Wall-clock limit for a source of `duration` seconds. Without a known
+    /// duration only stall detection applies.
+    pub fn wall_clock(&self, duration: Option<f64>) -> Option<Duration> {
+        let duration = duration.filter(|d| d.is_finite() && *d > 0.0)?;
+        // A limit too large to represent is no limit at all
+        Duration::try_from_secs_f64(duration * self.factor).ok().map(|limit| limit.max(self.min))
+    }
+}
+
+/// Watches one ffmpeg run and says when it should be killed.
+#[derive(Debug)]
+pub struct Watchdog {
+    started: Instant,
+    limit: Option<Duration>,
+    stall: Duration,
+    out_time: f64,
+    last_advance: Instant,
+    finishing: bool,
+}
+
+impl Watchdog {
+    pub fn new(timeouts: &Timeouts, duration: Option<f64>, now: Instant) -> Self {
+        Watchdog {
+            started: now,
+            limit: timeouts.wall_clock(duration),
+            stall: timeouts.stall,
+            out_time: 0.0,
+            last_advance: now,
+            finishing: false,
+        }
+    }
+
+    /// Feeds the job's latest progress (`None` once ffmpeg has closed its progress
+    /// output). Returns why the job should be killed, if it should.
+    pub fn observe(&mut self, progress: Option<&Progress>, now: Instant) -> Option<String> {
+        match progress {
+            Some(p) if p.finished => self.finishing = true,
+            Some(p) if p.out_time > self.out_time => {
+                self.out_time = p.out_time;
+                self.last_advance = now;
+            }
+            // Progress closed after having advanced: ffmpeg is writing the trailer
+            None if self.out_time > 0.0 => self.finishing = true,
+            _ => {}
+        }
+
+        if let Some(limit) = self.limit.filter(|limit| now.duration_since(self.started) > *limit) {
+            return Some(format!("exceeded the {}s time limit", limit.as_secs()));
+        }
+        let idle = now.duration_since(self.last_advance);
+        if !self.finishing && idle > self.stall {
+            return Some(format!("no progress for {}s (stuck at {:.1}s)", idle.as_secs(), self.out_time));
+        }
+        None
+    }
+}
+
+/// Kills `child` and everything in its process group. The child must have been
+/// spawned with `process_group(0)`.
+pub fn kill_process_group(child: &mut Child) {
+    // SAFETY: killpg only sends a signal; the group id is our child's pid
+    unsafe {
+        libc::killpg(child.id() as libc::pid_t, libc::SIGKILL);
+    }
+    let _ = child.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    fn at(out_time: f64) -> Progress {
+        Progress { out_time, ..Default::default() }
+    }
+
+    #[test]
+    fn test_wall_clock_scales_with_duration() {
+        let timeouts = Timeouts { factor: 2.0, min: Duration::from_secs(600), stall: Duration::from_secs(60) };
+        assert_eq!(timeouts.wall_clock(Some(3600.0)), Some(Duration::from_secs(7200)));
+        assert_eq!(timeouts.wall_clock(Some(30.0)), Some(Duration::from_secs(600)));
+        assert_eq!(timeouts.wall_clock(None), None);
+        let huge = Timeouts { factor: f64::MAX, ..timeouts };
+        assert_eq!(huge.wall_clock(Some(3600.0)), None);
+    }
+
+    #[test]
+    fn test_stall_is_detected_only_while_encoding() {
+        let timeouts = Timeouts { factor: 10.0, min: Duration::from_secs(3600), stall: Duration::from_secs(60) };
+        let t0 = Instant::now();
+        let secs = |s| t0 + Duration::from_secs(s);
+        let mut watchdog = Watchdog::new(&timeouts, Some(1200.0), t0);
+
+        assert_eq!(watchdog.observe(None, secs(30)), None);
+        assert_eq!(watchdog.observe(Some(&at(10.0)), secs(50)), None);
+        assert_eq!(watchdog.observe(Some(&at(10.0)), secs(100)), None);
+        let reason = watchdog.observe(Some(&at(10.0)), secs(111)).unwrap();
+        assert!(reason.starts_with("no progress for 61s"), "{}", reason);
+
+        // Once the progress output has closed, only the wall-clock limit applies
+        let mut watchdog = Watchdog::new(&timeouts, Some(1200.0), t0);
+        watchdog.observe(Some(&at(1200.0)), secs(100));
+        assert_eq!(watchdog.observe(None, secs(1000)), None);
+        assert!(watchdog.observe(None, secs(12_001)).unwrap().contains("time limit"));
+    }
+
+    #[test]
+    fn test_kill_process_group_takes_down_grandchildren() {
+        let mut child = Command::new("sh")
+            .args(["-c", "sleep 30 & sleep 30"])
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let start = Instant::now();
+        kill_process_group(&mut child);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // SAFETY: signal 0 only checks whether the group still exists
+        let alive = unsafe { libc::killpg(child.id() as libc::pid_t, 0) } == 0;
+        assert!(!alive);
+    }
+}
@@ -1,11 +1,19 @@
-use crate::processing::process_directory;
+use crate::config::load_config;
+use crate::ledger::load_failed;
+use crate::processing::{scan, JobSettings, Queue};
+use chrono::Local;
 use notify::{RecommendedWatcher, RecursiveMode, Watcher, Config, EventKind};
 use std::path::Path;
-use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::sync::mpsc::{channel, RecvTimeoutError};
 use std::thread;
 use std::time::Duration;
 
-pub fn start_watch(watch_dir: &str) {
+/// A single file copy fires dozens of events; they're collected for this long
+/// and answered with one scan.
+const DEBOUNCE: Duration = Duration::from_secs(1);
+
+pub fn start_watch(watch_dir: &str, queue: &Queue, settings: &Arc<JobSettings>) {
     println!("🕵️ Starting watcher on: {}", watch_dir);
 
     let (tx, rx) = channel();
@@ -17,31 +25,63 @@ pub fn start_watch(watch_dir: &str) {
         .watch(Path::new(watch_dir), RecursiveMode::Recursive)
         .expect("Failed to watch directory");
 
+    // Pick up whatever arrived while we weren't running
+    let mut settling = scan(watch_dir, queue, settings);
     loop {
-        match rx.recv() {
+        // Finished copies, due retries and deferred jobs raise no events, so
+        // rescan on a timer as well
+        match rx.recv_timeout(scan_timeout(settling)) {
             Ok(Ok(event)) => {
-                match event.kind {
-                    EventKind::Create(_) | EventKind::Modify(_) => {
-                        let dir = watch_dir.to_string();
-                        thread::spawn(move || {
-                            process_directory(&dir);
-                        });
-                    }
-                    _ => {}
+                if let EventKind::Create(_) | EventKind::Modify(_) = event.kind {
+                    thread::sleep(DEBOUNCE);
+                    let _ = rx.try_iter().count();
+                    settling = scan(watch_dir, queue, settings);
                 }
             }
             Ok(Err(e)) => eprintln!("Notify error: {:?}", e),
-            Err(e) => eprintln!("Receive error: {:?}", e),
+            Err(RecvTimeoutError::Timeout) => settling = scan(watch_dir, queue, settings),
+            Err(RecvTimeoutError::Disconnected) => {
+                eprintln!("Receive error: watcher stopped");
+                return;
+            }
         }
     }
 }
 
+/// How long to wait for events before scanning anyway: sources still arriving are
+/// checked again after the stability interval, failed ones when their retry is
+/// due, and everything else (deferred jobs, lapsed vetoes) every `RESCAN_SECS`.
+fn scan_timeout(settling: usize) -> Duration {
+    let cfg = load_config();
+    let now = Local::now();
+    let next_retry = load_failed()
+        .values()
+        .filter_map(|record| record.next_retry)
+        // Retries already due were queued by the last scan
+        .filter(|at| *at > now)
+        .min()
+        .map(|at| (at - now).to_std().unwrap_or_default());
+    wait_before_rescan(settling, cfg.stable_secs, cfg.rescan_secs, next_retry)
+}
+
+fn wait_before_rescan(settling: usize, stable_secs: u64, rescan_secs: u64, next_retry: Option<Duration>) -> Duration {
+    let mut wait = Duration::from_secs(rescan_secs.max(1));
+    if settling > 0 {
+        wait = wait.min(Duration::from_secs(stable_secs.max(1)));
+    }
+    if let Some(retry) = next_retry {
+        // A moment late, so the retry is due by the time we look
+        wait = wait.min(retry + Duration::from_secs(1));
+    }
+    wait
+}
+
 /// Fallback polling-based watcher
-pub fn start_watch_with_fallback(watch_dir: &str) {
+pub fn start_watch_with_fallback(watch_dir: &str, queue: &Queue, settings: &Arc<JobSettings>) {
     println!("🔁 SMB mode detected — using polling fallback every 30 seconds");
 
     loop {
-        process_directory(watch_dir);
+        scan(watch_dir, queue, settings);
         thread::sleep(Duration::from_secs(30));
     }
 }
@@ -50,6 +90,7 @@ pub fn start_watch_with_fallback(watch_dir: &str) {
 mod tests {
     use super::*;
     use std::fs::{self, File};
+    use std::sync::Arc;
     use std::time::Duration;
     use std::thread;
 
@@ -66,18 +107,27 @@ mod tests {
         assert!(watcher.is_ok());
     }
 
+    #[test]
+    fn test_rescan_waits_for_the_earliest_reason() {
+        assert_eq!(wait_before_rescan(0, 15, 300, None), Duration::from_secs(300));
+        assert_eq!(wait_before_rescan(2, 15, 300, None), Duration::from_secs(15));
+        assert_eq!(wait_before_rescan(2, 15, 300, Some(Duration::from_secs(4))), Duration::from_secs(5));
+    }
+
     #[test]
     fn test_fallback_processes_directory() {
         let test_dir = "/tmp/test-watcher-fallback";
         std::env::set_var("WATCH_DIR", test_dir);
         fs::create_dir_all(test_dir).unwrap();
 
-        // Create dummy file to trigger process_directory
+        // Create dummy file to trigger a scan
         create_test_file(test_dir, "sample.mkv");
 
         // Spawn fallback watcher and run just once (cancel immediately after)
+        let queue = Arc::new(Queue::new());
+        let settings = Arc::new(JobSettings::from_config(&load_config()));
         let handle = thread::spawn(move || {
-            start_watch_with_fallback(test_dir); // this runs in a loop
+            start_watch_with_fallback(test_dir, &queue, &settings); // this runs in a loop
         });
 
         // Allow one iteration of the fallback
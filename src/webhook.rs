@@ -0,0 +1,183 @@
+use crate::hooks::HookEvent;
+use crate::joblog::JobLog;
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+pub const SIGNATURE_HEADER: &str = "X-Transcoder-Signature";
+pub const EVENT_HEADER: &str = "X-Transcoder-Event";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// However many retries are configured, never wait longer than this between two.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// HTTP endpoints that receive job events as JSON (the same payload hooks get on stdin).
+///
+/// Each URL gets up to `1 + retries` attempts, waiting `backoff`, `2 × backoff`,
+/// `4 × backoff`, ... (at most five minutes) in between. With a `secret`, every request is signed with
+/// `X-Transcoder-Signature: sha256=<hex HMAC-SHA256 of the body>`. Every attempt
+/// is recorded in the delivery log.
+///
+/// Jobs hand their events to `send`, which delivers them one after another on a
+/// background thread; events still waiting there when the process exits are lost.
+#[derive(Debug, Clone)]
+pub struct Webhooks {
+    pub urls: Vec<String>,
+    pub secret: Option<String>,
+    pub retries: u32,
+    pub backoff: Duration,
+    pub log: JobLog,
+}
+
+impl Webhooks {
+    /// Webhooks with the default backoff, logging deliveries to `log_path`.
+    pub fn new(urls: Vec<String>, secret: Option<String>, retries: u32, log_path: &Path) -> Self {
+        Webhooks {
+            urls,
+            secret,
+            retries,
+            backoff: Duration::from_secs(2),
+            log: JobLog::at(log_path),
+        }
+    }
+
+    /// How long to wait after failed attempt number `attempt` (1-based).
+    pub fn delay_after(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.backoff.saturating_mul(factor).min(MAX_BACKOFF)
+    }
+
+    /// Queues `event` for the delivery thread, so slow or failing endpoints (and
+    /// their backoff) never hold up the job that raised it.
+    pub fn send(&self, event: HookEvent) {
+        if self.urls.is_empty() {
+            return;
+        }
+        let mut outbox = OUTBOX.lock().unwrap_or_else(|e| e.into_inner());
+        let sender = outbox.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel::<(Webhooks, HookEvent)>();
+            thread::spawn(move || {
+                for (webhooks, event) in receiver {
+                    webhooks.deliver(&event);
+                }
+            });
+            sender
+        });
+        if sender.send((self.clone(), event)).is_err() {
+            println!("📭 Webhook delivery thread is gone, dropping event");
+            *outbox = None;
+        }
+    }
+
+    /// Sends `event` to every URL. Returns, per URL, the number of attempts it
+    /// took or the last error.
+    pub fn deliver(&self, event: &HookEvent) -> Vec<Result<u32, String>> {
+        if self.urls.is_empty() {
+            return Vec::new();
+        }
+        let body = event.to_json();
+        let signature = self.secret.as_deref().map(|secret| signature(secret, &body));
+
+        self.urls
+            .iter()
+            .map(|url| {
+                let mut attempt = 0;
+                loop {
+                    attempt += 1;
+                    let result = post(url, event.event, &body, signature.as_deref());
+                    let status = match &result {
+                        Ok(()) => "delivered".to_string(),
+                        Err(e) => e.message.clone(),
+                    };
+                    self.log.line(&format!("{} {} attempt {}: {}", event.event, url, attempt, status));
+
+                    match result {
+                        Ok(()) => return Ok(attempt),
+                        Err(e) if !e.retryable || attempt > self.retries => {
+                            println!("📭 Webhook {} for {} failed: {}", url, event.event, e.message);
+                            return Err(e.message);
+                        }
+                        Err(_) => thread::sleep(self.delay_after(attempt)),
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+static OUTBOX: Mutex<Option<Sender<(Webhooks, HookEvent)>>> = Mutex::new(None);
+
+struct DeliveryError {
+    message: String,
+    /// Client errors (other than timeouts and rate limits) won't go away by retrying.
+    retryable: bool,
+}
+
+fn post(url: &str, event: &str, body: &str, signature: Option<&str>) -> Result<(), DeliveryError> {
+    let mut request = ureq::post(url)
+        .timeout(REQUEST_TIMEOUT)
+        .set("Content-Type", "application/json")
+        .set(EVENT_HEADER, event);
+    if let Some(signature) = signature {
+        request = request.set(SIGNATURE_HEADER, signature);
+    }
+
+    match request.send_string(body) {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(code, _)) => Err(DeliveryError {
+            message: format!("HTTP {}", code),
+            retryable: !(400..500).contains(&code) || code == 408 || code == 429,
+        }),
+        Err(e) => Err(DeliveryError { message: e.to_string(), retryable: true }),
+    }
+}
+
+/// `sha256=<hex>` HMAC-SHA256 of `body` keyed with `secret`.
+pub fn signature(secret: &str, body: &str) -> String {
+    let mac = hmac_sha256::HMAC::mac(body.as_bytes(), secret.as_bytes());
+    let hex: String = mac.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_matches_rfc_4231() {
+        assert_eq!(
+            signature("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        let webhooks = Webhooks::new(Vec::new(), None, 100, Path::new("/dev/null"));
+        assert_eq!(webhooks.delay_after(1), Duration::from_secs(2));
+        assert_eq!(webhooks.delay_after(3), Duration::from_secs(8));
+        assert_eq!(webhooks.delay_after(40), MAX_BACKOFF);
+        assert_eq!(webhooks.delay_after(u32::MAX), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_unreachable_url_gives_up_after_retries() {
+        let mut webhooks = Webhooks::new(
+            vec!["http://127.0.0.1:9/".into()],
+            None,
+            2,
+            &std::env::temp_dir().join("test_webhook_unreachable").join("deliveries.log"),
+        );
+        webhooks.backoff = Duration::from_millis(10);
+
+        let results = webhooks.deliver(&HookEvent { event: "job-failed", ..Default::default() });
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+
+        let log = std::fs::read_to_string(webhooks.log.path()).unwrap();
+        assert!(log.contains("job-failed http://127.0.0.1:9/ attempt 3:"), "{}", log);
+        let _ = std::fs::remove_dir_all(std::env::temp_dir().join("test_webhook_unreachable"));
+    }
+}
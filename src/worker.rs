@@ -0,0 +1,330 @@
+use crate::coordinator::WORKER_HEADER;
+use crate::shutdown;
+use crate::thumbnails::ThumbnailTarget;
+use crate::transcode::{transcode_file, JobAction, JobOptions, JobOutcome, TranscodeError};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long an idle worker waits before asking for work again.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Transfers have no overall limit, only on each read or write stalling.
+const IO_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A job as handed out by the coordinator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteJob {
+    pub id: u64,
+    /// Paths on the coordinator's machine.
+    pub source: PathBuf,
+    pub subtitle: Option<PathBuf>,
+    pub output: PathBuf,
+    /// Name and fingerprint of the coordinator's profile for this job.
+    pub profile: String,
+    pub profile_fingerprint: String,
+}
+
+/// Parses a claim response (see `coordinator::describe`).
+pub fn parse_job(text: &str) -> Option<RemoteJob> {
+    let mut id = None;
+    let mut source = None;
+    let mut subtitle = None;
+    let mut output = None;
+    let mut profile = String::new();
+    let mut profile_fingerprint = String::new();
+    for (key, value) in text.lines().filter_map(|l| l.split_once('=')) {
+        match key {
+            "id" => id = value.parse().ok(),
+            "source" => source = Some(PathBuf::from(value)),
+            "subtitle" if !value.is_empty() => subtitle = Some(PathBuf::from(value)),
+            "output" => output = Some(PathBuf::from(value)),
+            "profile" => profile = value.to_string(),
+            "profile_fingerprint" => profile_fingerprint = value.to_string(),
+            _ => {}
+        }
+    }
+    Some(RemoteJob { id: id?, source: source?, subtitle, output: output?, profile, profile_fingerprint })
+}
+
+/// The body of `/jobs/<id>/done`: what the worker did and the source's duration.
+pub fn describe_outcome(outcome: &JobOutcome) -> String {
+    let action = match outcome.action {
+        JobAction::Encoded => "encoded",
+        JobAction::Skipped => "skipped",
+    };
+    let duration = outcome.media_duration.map(|d| format!("{:.3}", d)).unwrap_or_default();
+    format!("action={}\nduration={}\n", action, duration)
+}
+
+/// Reads a `/jobs/<id>/done` body back into an action and source duration.
+pub fn parse_outcome(text: &str) -> (JobAction, Option<f64>) {
+    let mut action = JobAction::Encoded;
+    let mut duration = None;
+    for (key, value) in text.lines().filter_map(|l| l.split_once('=')) {
+        match (key, value) {
+            ("action", "skipped") => action = JobAction::Skipped,
+            ("duration", value) => duration = value.parse().ok().filter(|d: &f64| d.is_finite()),
+            _ => {}
+        }
+    }
+    (action, duration)
+}
+
+/// A process that takes jobs from a coordinator instead of watching a folder.
+///
+/// Sources are downloaded into `work_dir` and outputs uploaded, unless
+/// `shared_paths` is set and the coordinator's paths exist here too (same NFS or
+/// SMB mount), in which case they are used in place.
+#[derive(Debug, Clone)]
+pub struct RemoteWorker {
+    /// Coordinator base URL, e.g. `http://nas:7878`.
+    pub coordinator: String,
+    pub name: String,
+    pub work_dir: PathBuf,
+    pub shared_paths: bool,
+    pub heartbeat: Duration,
+    agent: ureq::Agent,
+}
+
+/// What to tell the coordinator about a job.
+enum Report {
+    /// Upload this local output.
+    Upload(PathBuf),
+    /// The output was written in place on shared storage.
+    Done(JobOutcome),
+    Failed(String),
+    /// The job-started hook refused it, for this reason.
+    Vetoed(String),
+    Released,
+    /// This worker's profile differs from the coordinator's, as described.
+    Mismatch(String),
+}
+
+impl RemoteWorker {
+    pub fn new(coordinator: &str, name: &str, work_dir: &Path, shared_paths: bool, heartbeat: Duration) -> Self {
+        RemoteWorker {
+            coordinator: coordinator.trim_end_matches('/').to_string(),
+            name: name.to_string(),
+            work_dir: work_dir.to_path_buf(),
+            shared_paths,
+            heartbeat,
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(CONNECT_TIMEOUT)
+                .timeout_read(IO_TIMEOUT)
+                .timeout_write(IO_TIMEOUT)
+                .build(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.coordinator, path)
+    }
+
+    /// Starts `count` threads that each run one job at a time until shutdown.
+    pub fn start(self: &Arc<Self>, count: usize) -> Vec<JoinHandle<()>> {
+        (0..count.max(1))
+            .map(|_| {
+                let worker = Arc::clone(self);
+                thread::spawn(move || {
+                    while !shutdown::requested() {
+                        match worker.run_one() {
+                            Ok(true) => {}
+                            Ok(false) => thread::sleep(POLL_INTERVAL),
+                            Err(e) => {
+                                println!("📡 Coordinator unreachable: {}", e);
+                                thread::sleep(POLL_INTERVAL);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Claims and runs one job. Returns false when the coordinator had none, or
+    /// only one for a profile this worker doesn't have.
+    pub fn run_one(&self) -> Result<bool, String> {
+        let response = self
+            .agent
+            .post(&self.url("/jobs/claim"))
+            .set(WORKER_HEADER, &self.name)
+            .call()
+            .map_err(|e| e.to_string())?;
+        if response.status() == 204 {
+            return Ok(false);
+        }
+        let text = response.into_string().map_err(|e| e.to_string())?;
+        let job = parse_job(&text).ok_or_else(|| format!("unreadable job: {:?}", text))?;
+        println!("📥 Got job {}: {}", job.id, job.source.display());
+
+        // Encoding with other settings than the coordinator's would publish the wrong output
+        let options = JobOptions::detect();
+        let fingerprint = options.profile.fingerprint();
+        if (job.profile.as_str(), job.profile_fingerprint.as_str()) != (options.profile.name.as_str(), fingerprint.as_str()) {
+            let mismatch = format!(
+                "job is for profile {} ({}), this worker has {} ({})",
+                job.profile, job.profile_fingerprint, options.profile.name, fingerprint
+            );
+            println!("⚠️ Handing back job {}: {}", job.id, mismatch);
+            self.report(&job, Report::Mismatch(mismatch))?;
+            return Ok(false);
+        }
+
+        // Heartbeats keep the job ours until reported; a 410 means it was given away
+        let lost = Arc::new(AtomicBool::new(false));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let heartbeat = {
+            let worker = self.clone();
+            let lost = Arc::clone(&lost);
+            let url = self.url(&format!("/jobs/{}/heartbeat", job.id));
+            thread::spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(worker.heartbeat) {
+                    if let Err(ureq::Error::Status(410, _)) =
+                        worker.agent.post(&url).set(WORKER_HEADER, &worker.name).call()
+                    {
+                        lost.store(true, Ordering::SeqCst);
+                        break;
+                    }
+                }
+            })
+        };
+
+        let job_dir = self.work_dir.join(job.id.to_string());
+        let report = self.process(&job, &job_dir, options);
+        let result = if lost.load(Ordering::SeqCst) {
+            println!("💔 Job {} was reassigned, dropping the result", job.id);
+            Ok(())
+        } else {
+            self.report(&job, report)
+        };
+        let _ = stop.send(());
+        let _ = heartbeat.join();
+        let _ = fs::remove_dir_all(&job_dir);
+        result.map(|()| true)
+    }
+
+    fn process(&self, job: &RemoteJob, job_dir: &Path, options: JobOptions) -> Report {
+        let shared = self.shared_paths && job.source.exists();
+        let (input, subtitle) = if shared {
+            (job.source.clone(), job.subtitle.clone())
+        } else {
+            match self.download(job, job_dir) {
+                Ok(paths) => paths,
+                Err(e) => return Report::Failed(format!("download failed: {}", e)),
+            }
+        };
+
+        let mut options = JobOptions { subtitle, ..options };
+        if !shared {
+            // The coordinator makes them once the output is uploaded
+            options.profile.thumbnails = ThumbnailTarget::Off;
+        }
+        match transcode_file(&input, &options) {
+            Ok(outcome) if shared => Report::Done(outcome),
+            Ok(outcome) => Report::Upload(outcome.output),
+            Err(TranscodeError::Vetoed(reason)) => Report::Vetoed(reason),
+            Err(TranscodeError::Deferred(_) | TranscodeError::Interrupted) => Report::Released,
+            Err(e) => Report::Failed(e.to_string()),
+        }
+    }
+
+    /// Fetches the source (and subtitle) into `job_dir` under their original names.
+    fn download(&self, job: &RemoteJob, job_dir: &Path) -> Result<(PathBuf, Option<PathBuf>), String> {
+        fs::create_dir_all(job_dir).map_err(|e| e.to_string())?;
+        let fetch = |which: &str, remote: &Path| -> Result<PathBuf, String> {
+            let local = job_dir.join(remote.file_name().unwrap_or_default());
+            let response = self
+                .agent
+                .get(&self.url(&format!("/jobs/{}/{}", job.id, which)))
+                .set(WORKER_HEADER, &self.name)
+                .call()
+                .map_err(|e| e.to_string())?;
+            let mut file = File::create(&local).map_err(|e| e.to_string())?;
+            io::copy(&mut response.into_reader(), &mut file).map_err(|e| e.to_string())?;
+            Ok(local)
+        };
+        let input = fetch("source", &job.source)?;
+        let subtitle = job.subtitle.as_deref().map(|s| fetch("subtitle", s)).transpose()?;
+        Ok((input, subtitle))
+    }
+
+    fn report(&self, job: &RemoteJob, report: Report) -> Result<(), String> {
+        let request = |action: &str, method: &str| {
+            self.agent
+                .request(method, &self.url(&format!("/jobs/{}/{}", job.id, action)))
+                .set(WORKER_HEADER, &self.name)
+        };
+        let result = match &report {
+            Report::Upload(output) => {
+                let file = File::open(output).map_err(|e| e.to_string())?;
+                println!("📤 Uploading {}", output.display());
+                request("output", "PUT").send(file)
+            }
+            Report::Done(outcome) => request("done", "POST").send_string(&describe_outcome(outcome)),
+            Report::Failed(error) => {
+                println!("💥 Job {} failed: {}", job.id, error);
+                request("failed", "POST").send_string(error)
+            }
+            Report::Vetoed(reason) => request("vetoed", "POST").send_string(reason),
+            Report::Released => request("release", "POST").call(),
+            Report::Mismatch(mismatch) => request("mismatch", "POST").send_string(mismatch),
+        };
+        result.map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+/// This machine's host name, for telling workers apart in the coordinator's log.
+pub fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer is valid for its whole length and gethostname NUL-terminates within it
+    let ok = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) } == 0;
+    let name = ok
+        .then(|| String::from_utf8_lossy(buffer.split(|b| *b == 0).next().unwrap_or_default()).into_owned())
+        .filter(|n| !n.is_empty());
+    name.unwrap_or_else(|| "worker".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_job() {
+        let job = parse_job(
+            "id=7\nsource=/watch/a b.mkv\nsubtitle=\noutput=/watch/a b.mp4\nprofile=tv\nprofile_fingerprint=0123abcd\n",
+        )
+        .unwrap();
+        assert_eq!(
+            job,
+            RemoteJob {
+                id: 7,
+                source: PathBuf::from("/watch/a b.mkv"),
+                subtitle: None,
+                output: PathBuf::from("/watch/a b.mp4"),
+                profile: "tv".into(),
+                profile_fingerprint: "0123abcd".into(),
+            }
+        );
+        assert!(parse_job("id=7\n").is_none());
+    }
+
+    #[test]
+    fn test_outcome_round_trips() {
+        let outcome = JobOutcome {
+            action: JobAction::Skipped,
+            input: PathBuf::from("/watch/a.mkv"),
+            output: PathBuf::from("/watch/a.mp4"),
+            input_size: 10,
+            output_size: 5,
+            media_duration: Some(1425.5),
+            elapsed: Duration::from_secs(3),
+        };
+        assert_eq!(parse_outcome(&describe_outcome(&outcome)), (JobAction::Skipped, Some(1425.5)));
+        assert_eq!(parse_outcome(""), (JobAction::Encoded, None));
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::CString;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Estimated output size relative to the source, with headroom for bitrate spikes.
+const OUTPUT_SIZE_NUMERATOR: u64 = 6;
+const OUTPUT_SIZE_DENOMINATOR: u64 = 5;
+
+/// A private temp directory for one job, removed when dropped — after success,
+/// on error returns and while unwinding from a panic.
+#[derive(Debug)]
+pub struct JobWorkspace {
+    dir: PathBuf,
+}
+
+impl JobWorkspace {
+    /// Creates `<root>/<stem>-<hash of the full input path>`, so files with the
+    /// same name in different folders never share a workspace. Leftovers from a
+    /// previous run of the same input are cleared first.
+    pub fn create(root: &Path, input: &Path) -> io::Result<Self> {
+        let mut hasher = DefaultHasher::new();
+        input.hash(&mut hasher);
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        let dir = root.join(format!("{}-{:016x}", stem, hasher.finish()));
+
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+        Ok(JobWorkspace { dir })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for JobWorkspace {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.dir) {
+            if e.kind() != io::ErrorKind::NotFound {
+                println!("⚠️ Failed to clean up {}: {}", self.dir.display(), e);
+            }
+        }
+    }
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `path`.
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: c_path is a valid NUL-terminated string and stat is a valid out pointer
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+pub fn estimate_output_size(input_size: u64) -> u64 {
+    input_size / OUTPUT_SIZE_DENOMINATOR * OUTPUT_SIZE_NUMERATOR
+}
+
+/// Space a job needs as `(temp, destination)`. The temp dir holds the source copy
+/// and the staged output; the destination only needs room for the output when
+/// it's on a different filesystem (otherwise publishing is a rename).
+pub fn required_space(input_size: u64, same_filesystem: bool) -> (u64, u64) {
+    let output = estimate_output_size(input_size);
+    let temp = input_size + output;
+    if same_filesystem {
+        (temp, 0)
+    } else {
+        (temp, output)
+    }
+}
+
+/// Checks that a job with a source of `input_size` bytes fits in both `temp_root`
+/// and `dest_dir`. Returns a human-readable reason when it doesn't.
+pub fn preflight(input_size: u64, temp_root: &Path, dest_dir: &Path) -> Result<(), String> {
+    let device = |p: &Path| fs::metadata(p).map(|m| m.dev()).ok();
+    let same_filesystem = device(temp_root).is_some() && device(temp_root) == device(dest_dir);
+    let (temp_needed, dest_needed) = required_space(input_size, same_filesystem);
+
+    for (label, dir, needed) in [("temp", temp_root, temp_needed), ("destination", dest_dir, dest_needed)] {
+        if needed == 0 {
+            continue;
+        }
+        let available = free_space(dir).map_err(|e| format!("cannot check free space in {}: {}", dir.display(), e))?;
+        if available < needed {
+            return Err(format!(
+                "not enough space in {} dir {}: need {} MiB, {} MiB free",
+                label,
+                dir.display(),
+                needed / (1024 * 1024),
+                available / (1024 * 1024)
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workspace_is_unique_per_path_and_cleaned_up() {
+        let root = std::env::temp_dir().join("test_workspace_root");
+        let a = JobWorkspace::create(&root, Path::new("/shows/a/Episode 1.mkv")).unwrap();
+        let b = JobWorkspace::create(&root, Path::new("/shows/b/Episode 1.mkv")).unwrap();
+        assert_ne!(a.path(), b.path());
+
+        fs::write(a.path().join("Episode 1.mkv"), b"copy").unwrap();
+        let a_dir = a.path().to_path_buf();
+        drop(a);
+        assert!(!a_dir.exists());
+
+        let b_dir = b.path().to_path_buf();
+        let result = std::panic::catch_unwind(move || {
+            let _b = b;
+            panic!("encode blew up");
+        });
+        assert!(result.is_err());
+        assert!(!b_dir.exists());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_required_space() {
+        assert_eq!(required_space(1000, true), (2200, 0));
+        assert_eq!(required_space(1000, false), (2200, 1200));
+    }
+
+    #[test]
+    fn test_preflight_fails_when_space_is_short() {
+        let tmp = std::env::temp_dir();
+        assert!(free_space(&tmp).unwrap() > 0);
+        assert!(preflight(1, &tmp, &tmp).is_ok());
+
+        let err = preflight(u64::MAX / 4, &tmp, &tmp).unwrap_err();
+        assert!(err.starts_with("not enough space in temp dir"), "{}", err);
+    }
+}
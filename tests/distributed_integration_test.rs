@@ -0,0 +1,186 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tiny_http::Server;
+use video_transcoder::coordinator::{Coordinator, WORKER_HEADER};
+use video_transcoder::ledger::{clear_failed, load_failed, load_ledger, RetryPolicy};
+use video_transcoder::processing::{JobSettings, Queue, QueuedJob};
+use video_transcoder::transcode::JobOptions;
+use video_transcoder::webhook::Webhooks;
+use video_transcoder::worker::parse_job;
+
+/// Serves a coordinator on a free localhost port with `sources` queued.
+fn start_coordinator(sources: &[PathBuf], heartbeat_timeout: Duration) -> (String, Arc<Coordinator>) {
+    let settings = Arc::new(JobSettings {
+        options: JobOptions::detect(),
+        webhooks: Webhooks::new(Vec::new(), None, 0, &std::env::temp_dir().join("test_distributed_webhooks.log")),
+        retry_policy: RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(600),
+            max_delay: Duration::from_secs(3600),
+        },
+    });
+    let queue = Arc::new(Queue::new());
+    for source in sources {
+        let job = QueuedJob { subtitle: None, resume: false, settings: Arc::clone(&settings) };
+        queue.push(source.clone(), job);
+    }
+
+    let server = Server::http("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+    let coordinator = Arc::new(Coordinator::new(queue, heartbeat_timeout));
+    let serving = Arc::clone(&coordinator);
+    thread::spawn(move || serving.serve(server));
+    (url, coordinator)
+}
+
+fn sources(dir: &Path, names: &[&str]) -> Vec<PathBuf> {
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir_all(dir).unwrap();
+    names
+        .iter()
+        .map(|name| {
+            let path = dir.join(format!("{}.mkv", name));
+            fs::write(&path, format!("source of {}", name)).unwrap();
+            path
+        })
+        .collect()
+}
+
+fn post(url: &str, worker: &str) -> ureq::Response {
+    ureq::post(url).set(WORKER_HEADER, worker).call().unwrap()
+}
+
+#[test]
+fn test_workers_claim_distinct_jobs_and_upload() {
+    let dir = std::env::temp_dir().join("test_distributed_upload");
+    let paths = sources(&dir, &["test_distributed_a", "test_distributed_b"]);
+    let (url, coordinator) = start_coordinator(&paths, Duration::from_secs(60));
+
+    let first = parse_job(&post(&format!("{}/jobs/claim", url), "one").into_string().unwrap()).unwrap();
+    let second = parse_job(&post(&format!("{}/jobs/claim", url), "two").into_string().unwrap()).unwrap();
+    assert_ne!(first.source, second.source);
+    assert_eq!(post(&format!("{}/jobs/claim", url), "three").status(), 204);
+
+    let source = ureq::get(&format!("{}/jobs/{}/source", url, first.id)).call().unwrap();
+    let expected = fs::read_to_string(&first.source).unwrap();
+    assert_eq!(source.into_string().unwrap(), expected);
+
+    // Anything that doesn't verify as the encoded source is refused and requeued
+    let uploaded = ureq::put(&format!("{}/jobs/{}/output", url, first.id))
+        .set(WORKER_HEADER, "one")
+        .send_bytes(b"encoded");
+    assert!(matches!(uploaded, Err(ureq::Error::Status(500, _))));
+    assert!(!first.output.exists());
+    let leftovers = fs::read_dir(&dir).unwrap().flatten().filter(|e| e.file_name().to_string_lossy().ends_with(".tmp"));
+    assert_eq!(leftovers.count(), 0);
+    assert_eq!(coordinator.assigned(), 1);
+    let base = first.source.file_stem().unwrap().to_string_lossy().into_owned();
+    assert!(!load_ledger().contains(&base));
+
+    // The refused job's id is spent; the job itself can be claimed again
+    let again = ureq::put(&format!("{}/jobs/{}/output", url, first.id)).send_bytes(b"late");
+    assert!(matches!(again, Err(ureq::Error::Status(410, _))));
+    let retry = parse_job(&post(&format!("{}/jobs/claim", url), "three").into_string().unwrap()).unwrap();
+    assert_eq!(retry.source, first.source);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_silent_worker_job_is_reassigned() {
+    let dir = std::env::temp_dir().join("test_distributed_reassign");
+    let paths = sources(&dir, &["test_distributed_silent"]);
+    let (url, coordinator) = start_coordinator(&paths, Duration::from_millis(300));
+
+    let job = parse_job(&post(&format!("{}/jobs/claim", url), "silent").into_string().unwrap()).unwrap();
+    assert_eq!(post(&format!("{}/jobs/{}/heartbeat", url, job.id), "silent").status(), 200);
+    assert_eq!(coordinator.reassign_stale(Instant::now()), 0);
+
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(coordinator.reassign_stale(Instant::now()), 1);
+    let heartbeat = ureq::post(&format!("{}/jobs/{}/heartbeat", url, job.id)).call();
+    assert!(matches!(heartbeat, Err(ureq::Error::Status(410, _))));
+
+    let retry = parse_job(&post(&format!("{}/jobs/claim", url), "other").into_string().unwrap()).unwrap();
+    assert_eq!(retry.source, job.source);
+    assert_ne!(retry.id, job.id);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_worker_processes_report_back() {
+    let dir = std::env::temp_dir().join("test_distributed_processes");
+    let names = ["test_distributed_proc_a", "test_distributed_proc_b", "test_distributed_proc_c"];
+    let paths = sources(&dir, &names);
+    let (url, coordinator) = start_coordinator(&paths, Duration::from_secs(60));
+
+    // These sources aren't video, so every job fails on the worker and is reported as such
+    let workers: Vec<_> = (0..2)
+        .map(|i| {
+            Command::new(env!("CARGO_BIN_EXE_video_transcoder"))
+                .args(["worker", &url])
+                .env("WORKER_NAME", format!("test-worker-{}", i))
+                .env("WORKER_DIR", dir.join(format!("worker-{}", i)))
+                .env("LOG_DIR", dir.join("logs"))
+                .env("THREADS", "1")
+                .env("HEARTBEAT_SECS", "1")
+                .stdout(Stdio::null())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let reported = || {
+        let failed = load_failed();
+        names.iter().all(|n| failed.contains_key(*n)) && coordinator.assigned() == 0
+    };
+    while !reported() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(200));
+    }
+    for mut worker in workers {
+        let _ = worker.kill();
+        let _ = worker.wait();
+    }
+    let done = reported();
+    for name in names {
+        clear_failed(name);
+    }
+    let _ = fs::remove_dir_all(&dir);
+    assert!(done, "workers did not report every job");
+}
+
+#[test]
+fn test_profile_mismatch_keeps_job_from_worker_then_fails_it() {
+    let dir = std::env::temp_dir().join("test_distributed_mismatch");
+    let paths = sources(&dir, &["test_distributed_mismatch"]);
+    let (url, coordinator) = start_coordinator(&paths, Duration::from_secs(60));
+    let turn_down = |worker: &str| {
+        let job = parse_job(&post(&format!("{}/jobs/claim", url), worker).into_string().unwrap()).unwrap();
+        let reported = ureq::post(&format!("{}/jobs/{}/mismatch", url, job.id))
+            .set(WORKER_HEADER, worker)
+            .send_string("job is for profile tv (0123abcd), this worker has default (4567ef01)")
+            .unwrap();
+        assert_eq!(reported.status(), 200);
+    };
+
+    // Not offered to the worker that turned it down, but still to the others
+    turn_down("one");
+    assert_eq!(post(&format!("{}/jobs/claim", url), "one").status(), 204);
+    turn_down("two");
+    assert!(!load_failed().contains_key("test_distributed_mismatch"));
+
+    // Enough workers disagree with the coordinator that the job fails
+    turn_down("three");
+    assert_eq!(post(&format!("{}/jobs/claim", url), "four").status(), 204);
+    assert_eq!(coordinator.assigned(), 0);
+    let failed = load_failed();
+    clear_failed("test_distributed_mismatch");
+    let _ = fs::remove_dir_all(&dir);
+    assert!(failed["test_distributed_mismatch"].last_error.starts_with("profile mismatch on 3 workers"));
+}
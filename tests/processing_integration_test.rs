@@ -1,18 +1,18 @@
 use std::fs::File;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::Path;
 use std::time::{Duration, Instant};
 
 use video_transcoder::ledger;
 
 /// Helper to create dummy MKV using FFmpeg lavfi testsrc.
-fn create_dummy_mkv(path: &PathBuf) {
+fn create_dummy_mkv(path: &Path) {
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent).expect("Failed to create MKV parent directory");
     }
 
     let output = std::process::Command::new("ffmpeg")
-        .args(&[
+        .args([
             "-f", "lavfi",
             "-i", "testsrc=duration=5:size=128x128:rate=15",
             "-pix_fmt", "yuv420p",
@@ -34,7 +34,7 @@ fn create_dummy_mkv(path: &PathBuf) {
 }
 
 /// Creates a dummy SRT subtitle file with one entry.
-fn create_dummy_srt(path: &PathBuf) {
+fn create_dummy_srt(path: &Path) {
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent).expect("Failed to create SRT parent directory");
     }
@@ -57,8 +57,9 @@ fn test_process_directory_creates_mp4() {
     let mkv_path = test_dir.join("sample.mkv");
     let srt_path = test_dir.join("sample.srt");
     let expected_output = test_dir.join("sample.mp4");
-    let converted_output = test_dir.join("sample.converted.mp4");
-    let log_path = test_dir.join("sample.log");
+    let log_path = test_dir.join("logs").join("sample.log");
+    std::env::set_var("LOG_DIR", test_dir.join("logs"));
+    std::env::set_var("STABLE_SECS", "0");
 
     create_dummy_mkv(&mkv_path);
     create_dummy_srt(&srt_path);
@@ -70,7 +71,7 @@ fn test_process_directory_creates_mp4() {
 
     let start = Instant::now();
     let timeout = Duration::from_secs(20);
-    while !(expected_output.exists() && converted_output.exists()) && start.elapsed() < timeout {
+    while !expected_output.exists() && start.elapsed() < timeout {
         std::thread::sleep(std::time::Duration::from_millis(200));
     }
 
@@ -0,0 +1,128 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use video_transcoder::hooks::HookEvent;
+use video_transcoder::webhook::{signature, Webhooks, SIGNATURE_HEADER};
+
+struct Request {
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Answers one request per status in `statuses`, sending each request it received back.
+fn serve(statuses: Vec<u16>) -> (String, mpsc::Receiver<Request>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        for status in statuses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut headers = Vec::new();
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let Some((name, value)) = line.trim_end().split_once(": ") else {
+                    break;
+                };
+                headers.push((name.to_string(), value.to_string()));
+            }
+            let length = headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case("content-length"))
+                .map(|(_, v)| v.parse().unwrap())
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut stream = reader.into_inner();
+            write!(stream, "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+            tx.send(Request { headers, body: String::from_utf8(body).unwrap() }).unwrap();
+        }
+    });
+
+    (url, rx)
+}
+
+fn webhooks(urls: Vec<String>, log_dir: &str) -> Webhooks {
+    let log_dir = std::env::temp_dir().join(log_dir);
+    let _ = std::fs::remove_dir_all(&log_dir);
+    let mut webhooks = Webhooks::new(urls, Some("s3cret".into()), 3, &log_dir.join("deliveries.log"));
+    webhooks.backoff = Duration::from_millis(20);
+    webhooks
+}
+
+fn succeeded() -> HookEvent {
+    HookEvent {
+        event: "job-succeeded",
+        input: PathBuf::from("/watch/movie.mkv"),
+        output: Some(PathBuf::from("/watch/movie.mp4")),
+        profile: "default".into(),
+        duration: Some(5400.0),
+        input_size: Some(4_000_000),
+        output_size: Some(3_000_000),
+        error: None,
+    }
+}
+
+#[test]
+fn test_webhook_retries_and_signs_payload() {
+    let (url, requests) = serve(vec![503, 200]);
+    let webhooks = webhooks(vec![url.clone()], "test_webhook_retries");
+
+    let results = webhooks.deliver(&succeeded());
+    assert_eq!(results, vec![Ok(2)]);
+
+    let first = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+    let second = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(first.body, second.body);
+    assert!(second.body.contains(r#""output_size":3000000"#), "{}", second.body);
+    assert_eq!(second.header("X-Transcoder-Event"), Some("job-succeeded"));
+    assert_eq!(
+        second.header(SIGNATURE_HEADER),
+        Some(signature("s3cret", &second.body).as_str())
+    );
+
+    let log = std::fs::read_to_string(webhooks.log.path()).unwrap();
+    assert!(log.contains(&format!("job-succeeded {} attempt 1: HTTP 503", url)), "{}", log);
+    assert!(log.contains(&format!("job-succeeded {} attempt 2: delivered", url)), "{}", log);
+}
+
+#[test]
+fn test_webhook_does_not_retry_client_errors() {
+    let (url, requests) = serve(vec![404]);
+    let webhooks = webhooks(vec![url], "test_webhook_client_error");
+
+    let results = webhooks.deliver(&succeeded());
+    assert_eq!(results, vec![Err("HTTP 404".to_string())]);
+    assert!(requests.recv_timeout(Duration::from_secs(5)).is_ok());
+    assert!(requests.recv_timeout(Duration::from_millis(200)).is_err());
+}
+
+#[test]
+fn test_sent_webhook_is_delivered_in_the_background() {
+    let (url, requests) = serve(vec![503, 200]);
+    let mut webhooks = webhooks(vec![url], "test_webhook_background");
+    webhooks.backoff = Duration::from_millis(300);
+
+    let start = Instant::now();
+    webhooks.send(succeeded());
+    assert!(start.elapsed() < Duration::from_millis(300));
+    assert!(requests.recv_timeout(Duration::from_secs(5)).is_ok());
+    assert!(requests.recv_timeout(Duration::from_secs(5)).is_ok());
+}